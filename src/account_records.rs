@@ -0,0 +1,343 @@
+use {
+    crate::{
+        decode_required,
+        error::{ResultExt, SnapshotDecodeError, SnapshotEncodeError},
+        fixed_bytes::decode_bytes,
+        read_length_delimited,
+        schema::solana::snapshot::AccountRecord as ProtoAccountRecord,
+        AccountsDbFields, SnapshotFields,
+    },
+    prost::Message,
+    solana_runtime::{
+        accounts_db::{
+            AccountShrinkThreshold, AccountStorageEntry, AccountsDb, AppendVecId,
+            IncludeSlotInHash, StoreReclaims,
+        },
+        accounts_index::AccountSecondaryIndexes,
+        append_vec::aligned_stored_size,
+        bank::Bank,
+    },
+    solana_sdk::{
+        account::{AccountSharedData, ReadableAccount},
+        clock::Slot,
+        genesis_config::ClusterType,
+        hash::Hash,
+        pubkey::Pubkey,
+    },
+    std::{
+        collections::HashMap,
+        io::{Read, Write},
+        ops::RangeInclusive,
+        path::PathBuf,
+        sync::{atomic::Ordering, Arc},
+    },
+};
+
+/// An account from the accounts section of a snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountRecord {
+    pub pubkey: Pubkey,
+    pub slot: Slot,
+    pub write_version: u64,
+    pub account: AccountSharedData,
+}
+
+/// Returns the storages whose accounts go in a snapshot of `bank`, in the order they are written
+///
+/// If `base_slot` is given, only the storages newer than it are returned, as for an incremental
+/// snapshot.
+///
+/// NOTE: Only accounts in storages are snapshotted, so the accounts cache must be flushed first.
+pub fn snapshot_storages(bank: &Bank, base_slot: Option<Slot>) -> Vec<Arc<AccountStorageEntry>> {
+    let mut storages = bank.get_snapshot_storages(base_slot);
+    storages.sort_unstable_by_key(|storage| (storage.slot(), storage.append_vec_id()));
+    storages
+}
+
+/// Writes every account in the bank's storages (newer than `base_slot`, if given) to `writer`, one
+/// length-delimited record at a time
+///
+/// Returns the number of records written.
+pub fn write_account_records(
+    bank: &Bank,
+    base_slot: Option<Slot>,
+    writer: &mut impl Write,
+) -> Result<u64, SnapshotEncodeError> {
+    let mut num_records = 0;
+    let mut buf = Vec::new();
+    for storage in snapshot_storages(bank, base_slot) {
+        for stored_account in storage.accounts.account_iter() {
+            let record = ProtoAccountRecord {
+                pubkey: stored_account.pubkey().to_bytes().into(),
+                slot: storage.slot(),
+                write_version: stored_account.write_version(),
+                account: Some(stored_account.into()),
+            };
+            buf.clear();
+            record
+                .encode_length_delimited(&mut buf)
+                .expect("encoding into a Vec cannot run out of space");
+            writer.write_all(&buf)?;
+            num_records += 1;
+        }
+    }
+    Ok(num_records)
+}
+
+/// Rebuilds an accounts db from the accounts section of a snapshot, with its storages in
+/// `account_paths`
+///
+/// Each slot's records are stored straight into a storage of their own and rooted, same as in the
+/// snapshotted bank.  The records must be in the order they were written, i.e. by slot.
+pub fn rebuild_accounts_db(
+    accounts_db_fields: &AccountsDbFields,
+    accounts_section: impl Read,
+    account_paths: Vec<PathBuf>,
+    cluster_type: &ClusterType,
+) -> Result<AccountsDb, SnapshotDecodeError> {
+    let mut accounts_db = new_accounts_db(account_paths, cluster_type);
+    store_account_records(
+        &accounts_db,
+        accounts_db_fields,
+        accounts_section,
+        0..=accounts_db_fields.slot,
+    )?;
+    finish_accounts_db(&mut accounts_db, accounts_db_fields);
+    Ok(accounts_db)
+}
+
+/// Rebuilds the accounts db of an incremental snapshot merged onto its full snapshot, from the
+/// accounts sections of both, with its storages in `account_paths`
+///
+/// The full snapshot's records are stored first, and must all be at or before its slot, then the
+/// incremental snapshot's, which must all be after it, same as the merged storages.  The full
+/// accounts hash is restored from the incremental snapshot persistence, so that the rebuilt bank
+/// can be snapshotted incrementally again.
+pub fn rebuild_merged_accounts_db(
+    merged_snapshot_fields: &SnapshotFields,
+    full_accounts_section: impl Read,
+    incremental_accounts_section: impl Read,
+    account_paths: Vec<PathBuf>,
+    cluster_type: &ClusterType,
+) -> Result<AccountsDb, SnapshotDecodeError> {
+    let persistence = merged_snapshot_fields
+        .bank
+        .incremental_snapshot_persistence
+        .as_ref()
+        .ok_or_else(|| SnapshotDecodeError::missing_field("incremental_snapshot_persistence"))
+        .within("bank")?;
+    let base_slot = persistence.full_slot;
+    let accounts_db_fields = &merged_snapshot_fields.accounts_db;
+    let mut accounts_db = new_accounts_db(account_paths, cluster_type);
+    store_account_records(
+        &accounts_db,
+        accounts_db_fields,
+        full_accounts_section,
+        0..=base_slot,
+    )
+    .within("full_snapshot")?;
+    store_account_records(
+        &accounts_db,
+        accounts_db_fields,
+        incremental_accounts_section,
+        base_slot + 1..=accounts_db_fields.slot,
+    )?;
+    accounts_db.set_accounts_hash_from_snapshot(
+        base_slot,
+        persistence.full_hash.clone(),
+        persistence.full_capitalization,
+    );
+    finish_accounts_db(&mut accounts_db, accounts_db_fields);
+    Ok(accounts_db)
+}
+
+fn new_accounts_db(account_paths: Vec<PathBuf>, cluster_type: &ClusterType) -> AccountsDb {
+    AccountsDb::new_with_config(
+        account_paths,
+        cluster_type,
+        AccountSecondaryIndexes::default(),
+        AccountShrinkThreshold::default(),
+        None,
+        None,
+        &Arc::default(),
+    )
+}
+
+/// Stores the records of an accounts section, one slot at a time, checking that their slots are in
+/// order and within `slots`
+fn store_account_records(
+    accounts_db: &AccountsDb,
+    accounts_db_fields: &AccountsDbFields,
+    accounts_section: impl Read,
+    slots: RangeInclusive<Slot>,
+) -> Result<(), SnapshotDecodeError> {
+    let include_slot_in_hash = if accounts_db_fields.include_slot_in_hash {
+        IncludeSlotInHash::IncludeSlot
+    } else {
+        IncludeSlotInHash::RemoveSlot
+    };
+    // NOTE: Records do not say which storage they came from, so each slot's records go into one
+    // storage, with the id of the slot's storage entry
+    let mut storage_ids = HashMap::with_capacity(accounts_db_fields.storage_entries.len());
+    for storage_entry in &accounts_db_fields.storage_entries {
+        storage_ids
+            .entry(storage_entry.slot)
+            .or_insert(storage_entry.id);
+    }
+    let mut slot_records: Vec<AccountRecord> = Vec::new();
+    let mut storage_id = AppendVecId::default();
+    for (i, account_record) in AccountRecords::new(accounts_section).enumerate() {
+        let account_record = account_record?;
+        if !slots.contains(&account_record.slot) {
+            return Err(SnapshotDecodeError::invalid_value(
+                format_args!("accounts[{i}].slot"),
+                format_args!("must be within {}..={}", slots.start(), slots.end()),
+            ));
+        }
+        let last_slot = slot_records.last().map(|last_record| last_record.slot);
+        if let Some(slot) = last_slot.filter(|slot| account_record.slot < *slot) {
+            return Err(SnapshotDecodeError::invalid_value(
+                format_args!("accounts[{i}].slot"),
+                format_args!("must not be older than the previous record's slot {slot}"),
+            ));
+        }
+        if last_slot != Some(account_record.slot) {
+            if last_slot.is_some() {
+                store_slot(accounts_db, storage_id, &slot_records, include_slot_in_hash);
+                slot_records.clear();
+            }
+            storage_id = *storage_ids.get(&account_record.slot).ok_or_else(|| {
+                SnapshotDecodeError::invalid_value(
+                    format_args!("accounts[{i}].slot"),
+                    "has no storage entry",
+                )
+            })?;
+        }
+        slot_records.push(account_record);
+    }
+    if !slot_records.is_empty() {
+        store_slot(accounts_db, storage_id, &slot_records, include_slot_in_hash);
+    }
+    Ok(())
+}
+
+/// Roots the snapshot's slot, and restores what the snapshotted bank had for it
+fn finish_accounts_db(accounts_db: &mut AccountsDb, accounts_db_fields: &AccountsDbFields) {
+    let slot = accounts_db_fields.slot;
+    accounts_db.add_root(slot);
+    let next_id = accounts_db_fields
+        .storage_entries
+        .iter()
+        .map(|storage_entry| storage_entry.id.saturating_add(1))
+        .max()
+        .unwrap_or_default();
+    accounts_db.next_id.store(next_id, Ordering::Release);
+
+    // NOTE: The stats and the accounts delta hash are whatever the snapshotted bank had, not what
+    // storing the records again produced.  The accounts hash is left out, since it may only be a
+    // placeholder, and writing an archive calculates it again anyway.
+    accounts_db
+        .write_version
+        .store(accounts_db_fields.write_version, Ordering::Release);
+    accounts_db
+        .set_accounts_delta_hash_from_snapshot(slot, accounts_db_fields.accounts_delta_hash.into());
+    accounts_db
+        .update_bank_hash_stats_from_snapshot(slot, accounts_db_fields.bank_hash_stats.clone());
+}
+
+/// Stores one slot's records in a new storage with id `storage_id`, with their write versions, and
+/// roots the slot
+///
+/// NOTE: Storing through the accounts cache would assign new write versions when it is flushed,
+/// so the records go straight into a storage sized to fit them instead.
+fn store_slot(
+    accounts_db: &AccountsDb,
+    storage_id: AppendVecId,
+    records: &[AccountRecord],
+    include_slot_in_hash: IncludeSlotInHash,
+) {
+    let slot = records[0].slot;
+    let size = records
+        .iter()
+        .map(|record| aligned_stored_size(record.account.data().len()) as u64)
+        .sum();
+    let storage = Arc::new(AccountStorageEntry::new(
+        &accounts_db.paths[0],
+        slot,
+        storage_id,
+        AccountsDb::page_align(size),
+    ));
+    accounts_db.storage.insert(slot, Arc::clone(&storage));
+    let accounts: Vec<_> = records
+        .iter()
+        .map(|record| (&record.pubkey, &record.account))
+        .collect();
+    let write_versions: Vec<_> = records.iter().map(|record| record.write_version).collect();
+    accounts_db.store_accounts_frozen(
+        (slot, accounts.as_slice(), include_slot_in_hash),
+        None::<Vec<&Hash>>,
+        &storage,
+        Some(Box::new(write_versions.into_iter())),
+        StoreReclaims::Default,
+    );
+    accounts_db.add_root(slot);
+}
+
+/// Lazily reads length-delimited account records, until `reader` is exhausted
+///
+/// Only one record is held in memory at a time.  Iteration stops after the first error.
+pub struct AccountRecords<R> {
+    reader: R,
+    buf: Vec<u8>,
+    index: usize,
+    done: bool,
+}
+
+impl<R: Read> AccountRecords<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            index: 0,
+            done: false,
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Option<AccountRecord>, SnapshotDecodeError> {
+        if !read_length_delimited(&mut self.reader, &mut self.buf)? {
+            return Ok(None);
+        }
+        let record = ProtoAccountRecord::decode(self.buf.as_slice())?;
+        AccountRecord::try_from(record).map(Some)
+    }
+}
+
+impl<R: Read> Iterator for AccountRecords<R> {
+    type Item = Result<AccountRecord, SnapshotDecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let index = self.index;
+        self.index += 1;
+        let result = self
+            .read_record()
+            .within(format_args!("accounts[{index}]"))
+            .transpose();
+        self.done = !matches!(result, Some(Ok(_)));
+        result
+    }
+}
+
+impl TryFrom<ProtoAccountRecord> for AccountRecord {
+    type Error = SnapshotDecodeError;
+    fn try_from(record: ProtoAccountRecord) -> Result<Self, Self::Error> {
+        Ok(Self {
+            pubkey: decode_bytes(&record.pubkey, "pubkey")?,
+            slot: record.slot,
+            write_version: record.write_version,
+            account: decode_required(record.account, "account")?,
+        })
+    }
+}
@@ -0,0 +1,63 @@
+use crate::schema::solana::snapshot::{
+    Bank as ProtoBank, EpochStake as ProtoEpochStake, Stakes as ProtoStakes,
+};
+
+/// How a snapshot is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Repeated fields that come from hash maps are written in whatever order the maps iterate
+    Unsorted,
+    /// The same bank always encodes to the same bytes, so snapshot files can be hashed and deduped
+    ///
+    /// Repeated fields that come from hash maps are sorted by key, and the header's creation time
+    /// is left out.
+    Canonical,
+}
+
+/// Sorts the repeated fields of `bank` that come from hash maps by key
+///
+/// NOTE: Keys are compared by their encoded bytes, which orders pubkeys and hashes the same as the
+/// runtime does.
+pub fn canonicalize_bank(bank: &mut ProtoBank) {
+    bank.ancestors
+        .sort_unstable_by_key(|ancestor| ancestor.slot);
+    if let Some(blockhash_queue) = &mut bank.blockhash_queue {
+        blockhash_queue
+            .ages
+            .sort_unstable_by(|a, b| a.hash.cmp(&b.hash));
+    }
+    if let Some(stakes) = &mut bank.stakes {
+        canonicalize_stakes(stakes);
+    }
+    bank.epoch_stakes
+        .sort_unstable_by_key(|epoch_stake| epoch_stake.epoch);
+    bank.epoch_stakes
+        .iter_mut()
+        .for_each(canonicalize_epoch_stake);
+}
+
+fn canonicalize_stakes(stakes: &mut ProtoStakes) {
+    stakes
+        .vote_accounts
+        .sort_unstable_by(|a, b| a.pubkey.cmp(&b.pubkey));
+    stakes
+        .stake_delegations
+        .sort_unstable_by(|a, b| a.pubkey.cmp(&b.pubkey));
+}
+
+fn canonicalize_epoch_stake(epoch_stake: &mut ProtoEpochStake) {
+    if let Some(stakes) = &mut epoch_stake.stakes {
+        canonicalize_stakes(stakes);
+    }
+    // NOTE: Each node's vote accounts are a list in the runtime, but it is built by iterating a
+    // hash map, so it is in no particular order either
+    epoch_stake
+        .node_ids_to_vote_accounts
+        .sort_unstable_by(|a, b| a.node_id.cmp(&b.node_id));
+    for node_id_to_vote_accounts in &mut epoch_stake.node_ids_to_vote_accounts {
+        node_id_to_vote_accounts.vote_accounts.sort_unstable();
+    }
+    epoch_stake
+        .epoch_authorized_voters
+        .sort_unstable_by(|a, b| a.vote_account.cmp(&b.vote_account));
+}
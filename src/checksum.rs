@@ -0,0 +1,206 @@
+use {
+    crate::{
+        error::SnapshotDecodeError,
+        header::SCHEMA_VERSION,
+        read_length_delimited,
+        schema::solana::snapshot::{
+            checksums::SectionChecksum as ProtoSectionChecksum, Checksums as ProtoChecksums,
+            Header as ProtoHeader,
+        },
+        signing,
+    },
+    prost::Message,
+    solana_sdk::{hash::Hasher, signature::Keypair},
+    std::{
+        io::{self, Read, Seek, SeekFrom, Take, Write},
+        mem,
+    },
+};
+
+/// The size of the checksums' length, at the very end of a snapshot
+const CHECKSUMS_LEN_BYTES: u64 = mem::size_of::<u32>() as u64;
+
+/// Writes through to `inner`, hashing each section and the whole file, so that the checksums can
+/// be appended by `finish()`
+pub struct ChecksumWriter<W> {
+    inner: W,
+    file_hasher: Hasher,
+    section_hasher: Hasher,
+    section_len: u64,
+    sections: Vec<ProtoSectionChecksum>,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            file_hasher: Hasher::default(),
+            section_hasher: Hasher::default(),
+            section_len: 0,
+            sections: Vec::new(),
+        }
+    }
+
+    /// Ends the section named `name`, i.e. everything written since the previous section ended
+    pub fn end_section(&mut self, name: &str) {
+        self.sections.push(ProtoSectionChecksum {
+            name: name.to_string(),
+            len: mem::take(&mut self.section_len),
+            hash: mem::take(&mut self.section_hasher)
+                .result()
+                .to_bytes()
+                .into(),
+        });
+    }
+
+    /// Writes the checksums of everything written so far, signed by `signer` if given, then returns
+    /// the inner writer
+    pub fn finish(self, signer: Option<&Keypair>) -> io::Result<W> {
+        let (mut inner, mut checksums) = self.into_checksums();
+        if let Some(signer) = signer {
+            signing::sign_checksums(&mut checksums, signer);
+        }
+        let checksums = checksums.encode_to_vec();
+        let checksums_len = u32::try_from(checksums.len())
+            .expect("checksums are small, since there are only a few sections");
+        inner.write_all(&checksums)?;
+        inner.write_all(&checksums_len.to_le_bytes())?;
+        Ok(inner)
+    }
+
+    /// Returns the inner writer, and the unsigned checksums of everything written so far, without
+    /// writing them
+    fn into_checksums(self) -> (W, ProtoChecksums) {
+        let checksums = ProtoChecksums {
+            sections: self.sections,
+            file_hash: self.file_hasher.result().to_bytes().into(),
+            signature: None,
+        };
+        (self.inner, checksums)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.file_hasher.hash(&buf[..len]);
+        self.section_hasher.hash(&buf[..len]);
+        self.section_len += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads a snapshot's checksums from its end, and returns the length of everything before them,
+/// and the checksums
+pub fn read_checksums<R: Read + Seek>(
+    reader: &mut R,
+) -> Result<(u64, ProtoChecksums), SnapshotDecodeError> {
+    let checksums_end = reader
+        .seek(SeekFrom::End(0))?
+        .checked_sub(CHECKSUMS_LEN_BYTES)
+        .ok_or(SnapshotDecodeError::MissingChecksums)?;
+    reader.seek(SeekFrom::Start(checksums_end))?;
+    let mut checksums_len = [0; CHECKSUMS_LEN_BYTES as usize];
+    reader.read_exact(&mut checksums_len)?;
+    let checksums_len = u32::from_le_bytes(checksums_len);
+    let body_len = checksums_end
+        .checked_sub(u64::from(checksums_len))
+        .ok_or(SnapshotDecodeError::MissingChecksums)?;
+    reader.seek(SeekFrom::Start(body_len))?;
+    let mut checksums = vec![0; checksums_len as usize];
+    reader.read_exact(&mut checksums)?;
+    // NOTE: If the checksums are garbage, the snapshot was most likely cut short
+    let checksums = ProtoChecksums::decode(checksums.as_slice())
+        .map_err(|_| SnapshotDecodeError::MissingChecksums)?;
+    // NOTE: Garbage can still decode, but then its sections will not add up to the rest of the file
+    let sections_len = checksums
+        .sections
+        .iter()
+        .try_fold(0u64, |sections_len, section| {
+            sections_len.checked_add(section.len)
+        });
+    if checksums.sections.is_empty() || sections_len != Some(body_len) {
+        return Err(SnapshotDecodeError::MissingChecksums);
+    }
+    Ok((body_len, checksums))
+}
+
+/// Checks a snapshot against its checksums, and returns a reader of everything before them, to be
+/// decoded
+pub fn verify_checksums<R: Read + Seek>(reader: R) -> Result<Take<R>, SnapshotDecodeError> {
+    verify_split_checksums(reader).map(|(body, _checksums)| body)
+}
+
+/// Checks a snapshot against its checksums, and returns a reader of everything before them, and
+/// the checksums
+///
+/// The snapshot is hashed as it is read, one buffer at a time, so it is never all in memory.  Each
+/// section is checked before the whole file, so that a mismatch names the corrupt section.  The
+/// returned reader starts over at the beginning of the snapshot.
+pub fn verify_split_checksums<R: Read + Seek>(
+    mut reader: R,
+) -> Result<(Take<R>, ProtoChecksums), SnapshotDecodeError> {
+    let (body_len, checksums) = match check_checksums(&mut reader) {
+        Ok(checked) => checked,
+        Err(err) => return Err(older_version(&mut reader).unwrap_or(err)),
+    };
+    reader.rewind()?;
+    Ok((reader.take(body_len), checksums))
+}
+
+/// Checks a snapshot against its checksums, and returns the length of everything before them, and
+/// the checksums
+fn check_checksums(
+    reader: &mut (impl Read + Seek),
+) -> Result<(u64, ProtoChecksums), SnapshotDecodeError> {
+    let (body_len, checksums) = read_checksums(reader)?;
+    reader.rewind()?;
+    let mut body = reader.take(body_len);
+    let mut writer = ChecksumWriter::new(io::sink());
+    for section in &checksums.sections {
+        io::copy(&mut body.by_ref().take(section.len), &mut writer)?;
+        writer.end_section(&section.name);
+    }
+    let (_sink, actual) = writer.into_checksums();
+    if let Some(section) = checksums
+        .sections
+        .iter()
+        .zip(&actual.sections)
+        .find_map(|(expected, actual)| (expected != actual).then_some(expected))
+    {
+        return Err(SnapshotDecodeError::ChecksumMismatch {
+            section: section.name.clone(),
+        });
+    }
+    if body.limit() != 0 || actual.file_hash != checksums.file_hash {
+        return Err(SnapshotDecodeError::ChecksumMismatch {
+            section: "file".to_string(),
+        });
+    }
+    Ok((body_len, checksums))
+}
+
+/// Returns an error if the header says the snapshot is from a version before the checksums were
+/// added
+///
+/// Those versions have no checksums, so whatever is at the end of the file is misread as them.
+/// This is only checked once the checksums have failed, since until then the header is not
+/// trusted.
+fn older_version(reader: &mut (impl Read + Seek)) -> Option<SnapshotDecodeError> {
+    let mut buf = Vec::new();
+    reader.rewind().ok()?;
+    if !read_length_delimited(reader, &mut buf).ok()? {
+        return None;
+    }
+    let header = ProtoHeader::decode(buf.as_slice()).ok()?;
+    (1..SCHEMA_VERSION).contains(&header.version).then_some(
+        SnapshotDecodeError::UnsupportedVersion {
+            version: header.version,
+            supported: SCHEMA_VERSION,
+        },
+    )
+}
@@ -0,0 +1,729 @@
+use {
+    crate::{
+        account_records::{AccountRecord, AccountRecords},
+        canonical::Encoding,
+        checksum::{verify_checksums, verify_split_checksums},
+        convert::{
+            archive_to_snapshot, incremental_snapshot_to_archive, reencode_snapshot,
+            snapshot_to_archive,
+        },
+        decode_snapshot, decode_snapshot_message,
+        diff::{diff_bank_fields, slot_deltas_eq},
+        error::{ResultExt, SnapshotDecodeError},
+        fixed_bytes::FixedBytes,
+        header::{SnapshotHeader, ALL_SECTIONS},
+        merge::merge_incremental_snapshot,
+        read_required_message,
+        schema::solana::snapshot::{
+            header::Section, snapshot::Kind as ProtoSnapshotKind, Bank as ProtoBank,
+            Header as ProtoHeader, Snapshot as ProtoSnapshot,
+        },
+        signing::verify_signature,
+        snapshot_bank, stats,
+        test_bank::{genesis_test_bank, new_test_bank, test_genesis_config},
+        SnapshotFields, SnapshotKind,
+    },
+    clap::{Args, Parser, Subcommand},
+    solana_runtime::{
+        snapshot_archive_info::SnapshotArchiveInfoGetter,
+        snapshot_utils::{self, ArchiveFormat},
+        vote_account::VoteAccount,
+    },
+    solana_sdk::{
+        account::AccountSharedData,
+        clock::Slot,
+        genesis_config::GenesisConfig,
+        hash::Hash,
+        native_token::LAMPORTS_PER_SOL,
+        pubkey::Pubkey,
+        signature::{read_keypair_file, Keypair},
+    },
+    std::{
+        collections::{BTreeSet, HashMap},
+        env,
+        error::Error,
+        fmt::Display,
+        fs::{self, File},
+        io::{self, BufReader, BufWriter, Read, Write},
+        path::{Path, PathBuf},
+        process,
+    },
+};
+
+/// Prototyping serialization of Solana snapshots using Protocol Buffers
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Creates a snapshot of a test bank
+    Create {
+        /// Where to write the snapshot
+        output: PathBuf,
+        /// How many slots past genesis the bank is
+        #[arg(long, default_value_t = 21)]
+        slots: Slot,
+        /// Also write the test bank's genesis config into this ledger, e.g. for `--to-archive`
+        #[arg(long)]
+        ledger: Option<PathBuf>,
+        #[command(flatten)]
+        encode_args: EncodeArgs,
+    },
+    /// Prints a summary of a snapshot
+    Inspect {
+        snapshot: PathBuf,
+        /// How many of the most staked vote accounts to print
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Re-encodes a snapshot, e.g. to make it canonical or to sign it, or converts between a
+    /// validator's snapshot archives and snapshots
+    Convert {
+        /// A snapshot, or a full snapshot archive, i.e. `snapshot-<slot>-<hash>.tar.zst`
+        input: PathBuf,
+        /// The snapshot to write, or the directory to write a snapshot archive into
+        output: PathBuf,
+        #[command(flatten)]
+        archive_args: ArchiveArgs,
+        #[command(flatten)]
+        encode_args: EncodeArgs,
+    },
+    /// Checks a snapshot's checksums, and its signature if a trusted signer is given, then decodes
+    /// all of it
+    Verify {
+        snapshot: PathBuf,
+        /// Only accept the snapshot if it is signed by this pubkey
+        #[arg(long)]
+        trusted_signer: Option<Pubkey>,
+        /// The full snapshot that an incremental snapshot is based on, to check that it applies
+        #[arg(long)]
+        full_snapshot: Option<PathBuf>,
+    },
+    /// Prints the fields that differ between two snapshots
+    Diff { a: PathBuf, b: PathBuf },
+    /// Prints how many bytes each section, and the biggest fields within them, take
+    Stats { snapshot: PathBuf },
+}
+
+#[derive(Args, Debug)]
+struct EncodeArgs {
+    /// Encode canonically, so that the same bank always encodes to the same bytes
+    #[arg(long)]
+    canonical: bool,
+    /// Sign the snapshot with the keypair in this file
+    #[arg(long)]
+    keypair: Option<PathBuf>,
+    /// Leave out the accounts section, e.g. to only look at the bank
+    #[arg(long)]
+    no_accounts: bool,
+    /// Leave out the status cache
+    #[arg(long)]
+    no_status_cache: bool,
+}
+
+#[derive(Args, Debug)]
+struct ArchiveArgs {
+    /// Convert the snapshot into a full snapshot archive, written into the OUTPUT directory
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "incremental_archive",
+            "canonical",
+            "keypair",
+            "no_accounts",
+            "no_status_cache",
+        ],
+    )]
+    to_archive: bool,
+    /// The ledger with the genesis config, which is required to convert to or from a snapshot
+    /// archive
+    #[arg(long)]
+    ledger: Option<PathBuf>,
+    /// An incremental snapshot archive to apply onto the full snapshot archive
+    #[arg(long)]
+    incremental_archive: Option<PathBuf>,
+    /// The full snapshot that an incremental snapshot is based on, to apply the incremental
+    /// snapshot onto when converting it into a snapshot archive
+    #[arg(long, requires = "to_archive")]
+    full_snapshot: Option<PathBuf>,
+    /// Where to unpack snapshot archives, instead of a temporary directory that is removed after
+    #[arg(long)]
+    work_dir: Option<PathBuf>,
+}
+
+impl ArchiveArgs {
+    /// Loads the genesis config from the ledger, which must be given
+    fn genesis_config(&self) -> Result<GenesisConfig, Box<dyn Error>> {
+        let ledger = self
+            .ledger
+            .as_ref()
+            .ok_or("--ledger is required to convert to or from a snapshot archive")?;
+        Ok(GenesisConfig::load(ledger)?)
+    }
+
+    /// Checks that no archive args were given, for when neither side of a conversion is an archive
+    fn check_unused(&self) -> Result<(), Box<dyn Error>> {
+        let given = [
+            ("--ledger", self.ledger.is_some()),
+            ("--incremental-archive", self.incremental_archive.is_some()),
+            ("--work-dir", self.work_dir.is_some()),
+        ];
+        match given.into_iter().find(|(_arg, is_given)| *is_given) {
+            Some((arg, _is_given)) => {
+                Err(format!("{arg} is only used to convert to or from a snapshot archive").into())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Calls `f` with the work dir, which is a temporary one that is removed after, unless one was
+    /// given
+    fn with_work_dir<T, E: Into<Box<dyn Error>>>(
+        &self,
+        f: impl FnOnce(&Path) -> Result<T, E>,
+    ) -> Result<T, Box<dyn Error>> {
+        let Some(work_dir) = &self.work_dir else {
+            let work_dir = env::temp_dir().join(format!("protosnap-{}", process::id()));
+            let result = f(&work_dir);
+            // NOTE: Clean up even if converting failed, but report the conversion's error first
+            let removed = fs::remove_dir_all(&work_dir);
+            let value = result.map_err(Into::into)?;
+            removed?;
+            return Ok(value);
+        };
+        f(work_dir).map_err(Into::into)
+    }
+}
+
+impl EncodeArgs {
+    fn encoding(&self) -> Encoding {
+        if self.canonical {
+            Encoding::Canonical
+        } else {
+            Encoding::Unsorted
+        }
+    }
+
+    /// Returns the optional sections to write, which are all of them unless some are left out
+    fn sections(&self) -> BTreeSet<Section> {
+        ALL_SECTIONS
+            .into_iter()
+            .filter(|section| match section {
+                Section::Accounts => !self.no_accounts,
+                Section::StatusCache => !self.no_status_cache,
+                Section::Unspecified => false,
+            })
+            .collect()
+    }
+
+    fn signer(&self) -> Result<Option<Keypair>, Box<dyn Error>> {
+        self.keypair
+            .as_ref()
+            .map(|path| {
+                read_keypair_file(path)
+                    .map_err(|err| format!("failed to read keypair {}: {err}", path.display()))
+            })
+            .transpose()
+            .map_err(Into::into)
+    }
+}
+
+pub fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Command::Create {
+            output,
+            slots,
+            ledger,
+            encode_args,
+        } => create(&output, slots, ledger.as_deref(), &encode_args),
+        Command::Inspect { snapshot, top } => inspect(&snapshot, top),
+        Command::Convert {
+            input,
+            output,
+            archive_args,
+            encode_args,
+        } => {
+            if archive_args.to_archive {
+                convert_to_archive(&input, &output, &archive_args)
+            } else if is_full_snapshot_archive(&input) {
+                convert_archive(&input, &output, &archive_args, &encode_args)
+            } else if is_incremental_snapshot_archive(&input) {
+                Err(format!(
+                    "{} is an incremental snapshot archive, so give its full snapshot archive as \
+                     the input, and it as --incremental-archive",
+                    input.display()
+                )
+                .into())
+            } else {
+                archive_args.check_unused()?;
+                convert(&input, &output, &encode_args)
+            }
+        }
+        Command::Verify {
+            snapshot,
+            trusted_signer,
+            full_snapshot,
+        } => verify(&snapshot, trusted_signer.as_ref(), full_snapshot.as_deref()),
+        Command::Diff { a, b } => diff(&a, &b),
+        Command::Stats { snapshot } => print_stats(&snapshot),
+    }
+}
+
+fn create(
+    output: &Path,
+    num_slots: Slot,
+    ledger: Option<&Path>,
+    encode_args: &EncodeArgs,
+) -> Result<(), Box<dyn Error>> {
+    let genesis_config = test_genesis_config();
+    if let Some(ledger) = ledger {
+        fs::create_dir_all(ledger)?;
+        genesis_config.write(ledger)?;
+    }
+    let bank = new_test_bank(genesis_test_bank(&genesis_config), num_slots);
+    let signer = encode_args.signer()?;
+    write_output(output, |writer| {
+        snapshot_bank(
+            &bank,
+            &SnapshotKind::Full,
+            encode_args.sections(),
+            encode_args.encoding(),
+            signer.as_ref(),
+            writer,
+        )
+        .map_err(Into::into)
+    })?;
+    println!(
+        "wrote a snapshot of slot {} to {}",
+        bank.slot(),
+        output.display()
+    );
+    Ok(())
+}
+
+fn inspect(path: &Path, num_top_stakes: usize) -> Result<(), Box<dyn Error>> {
+    let (mut sections, checksums) = verify_split_checksums(open_snapshot(path)?)?;
+    // NOTE: Print the summary from the raw messages, and only decode them after, so that a snapshot
+    // that fails to decode can still be triaged
+    let mut buf = Vec::new();
+    let header: ProtoHeader = read_required_message(&mut sections, &mut buf, "header")?;
+    println!("version: {}", header.version);
+    println!("solana version: {}", header.solana_version);
+    println!("creation time: {}", header.creation_time);
+    let section_names: Vec<_> = header
+        .sections
+        .iter()
+        .map(|section| match Section::from_i32(*section) {
+            Some(section) => format!("{section:?}"),
+            None => format!("unknown ({section})"),
+        })
+        .collect();
+    if section_names.is_empty() {
+        println!("sections: none");
+    } else {
+        println!("sections: {}", section_names.join(", "));
+    }
+    let snapshot: ProtoSnapshot = read_required_message(&mut sections, &mut buf, "snapshot")?;
+    match &snapshot.kind {
+        Some(ProtoSnapshotKind::Full(_)) => println!("kind: full"),
+        Some(ProtoSnapshotKind::Incremental(incremental)) => println!(
+            "kind: incremental, based on slot {} with accounts hash {}",
+            incremental.base_slot,
+            fixed_bytes_string::<Hash>(&incremental.base_hash),
+        ),
+        None => println!("kind: none"),
+    }
+    match &snapshot.bank {
+        Some(bank) => print_bank_summary(bank, num_top_stakes),
+        None => println!("bank: none"),
+    }
+    match &checksums.signature {
+        Some(signature) => println!(
+            "signed by: {}",
+            fixed_bytes_string::<Pubkey>(&signature.signer)
+        ),
+        None => println!("signed by: nobody"),
+    }
+
+    let header = SnapshotHeader::try_from(header).within("header")?;
+    let snapshot_fields = decode_snapshot_message(header, snapshot)?;
+    println!(
+        "account records: {}",
+        count_account_records(&snapshot_fields, sections)?
+    );
+    Ok(())
+}
+
+/// Prints the bank's fields that matter for triage, with pubkeys and hashes in base58 and lamports
+/// in SOL
+///
+/// The bank is printed as it is encoded, without checking it, so fields that do not decode are
+/// printed as invalid rather than failing.
+fn print_bank_summary(bank: &ProtoBank, num_top_stakes: usize) {
+    println!("slot: {} (parent {})", bank.slot, bank.parent_slot);
+    println!("epoch: {}", bank.epoch);
+    println!("block height: {}", bank.block_height);
+    println!("bank hash: {}", fixed_bytes_string::<Hash>(&bank.hash));
+    println!(
+        "parent bank hash: {}",
+        fixed_bytes_string::<Hash>(&bank.parent_hash)
+    );
+    println!(
+        "collector: {}",
+        fixed_bytes_string::<Pubkey>(&bank.collector_id)
+    );
+    println!("capitalization: {}", sol(bank.capitalization));
+    let hard_forks: Vec<_> = bank
+        .hard_forks
+        .iter()
+        .map(|hard_fork| match hard_fork.count {
+            1 => hard_fork.slot.to_string(),
+            count => format!("{} (x{count})", hard_fork.slot),
+        })
+        .collect();
+    if hard_forks.is_empty() {
+        println!("hard forks: none");
+    } else {
+        println!("hard forks: {}", hard_forks.join(", "));
+    }
+    match &bank.blockhash_queue {
+        Some(blockhash_queue) => println!(
+            "blockhash queue: {} of at most {} hashes, last {}",
+            blockhash_queue.ages.len(),
+            blockhash_queue.max_age,
+            blockhash_queue.last_hash.as_ref().map_or_else(
+                || "none".to_string(),
+                |hash| fixed_bytes_string::<Hash>(hash)
+            ),
+        ),
+        None => println!("blockhash queue: none"),
+    }
+
+    let vote_accounts = bank
+        .stakes
+        .as_ref()
+        .map_or(&[][..], |stakes| &stakes.vote_accounts);
+    let mut staked_vote_accounts: Vec<_> = vote_accounts
+        .iter()
+        .filter(|vote_account| vote_account.stake > 0)
+        .collect();
+    let total_stake: u64 = staked_vote_accounts
+        .iter()
+        .map(|vote_account| vote_account.stake)
+        .sum();
+    println!(
+        "vote accounts: {}, {} staked, {} total stake",
+        vote_accounts.len(),
+        staked_vote_accounts.len(),
+        sol(total_stake),
+    );
+    staked_vote_accounts
+        .sort_unstable_by(|a, b| b.stake.cmp(&a.stake).then(a.pubkey.cmp(&b.pubkey)));
+    if !staked_vote_accounts.is_empty() {
+        println!("top stakes:");
+    }
+    for vote_account in staked_vote_accounts.into_iter().take(num_top_stakes) {
+        // NOTE: The vote state is only deserialized for its node pubkey, so its errors are ignored
+        let node_pubkey = vote_account
+            .vote_account
+            .clone()
+            .and_then(|account| AccountSharedData::try_from(account).ok())
+            .and_then(|account| VoteAccount::try_from(account).ok())
+            .and_then(|vote_account| vote_account.node_pubkey())
+            .map_or_else(
+                || "unknown".to_string(),
+                |node_pubkey| node_pubkey.to_string(),
+            );
+        println!(
+            "  {} (node {node_pubkey}): {}, {:.2}%",
+            fixed_bytes_string::<Pubkey>(&vote_account.pubkey),
+            sol(vote_account.stake),
+            vote_account.stake as f64 / total_stake as f64 * 100.0,
+        );
+    }
+}
+
+/// Formats a pubkey or hash field in base58, or says how long it is if that is the wrong length
+fn fixed_bytes_string<T: FixedBytes + Display>(bytes: &[u8]) -> String {
+    T::from_fixed_bytes(bytes).map_or_else(
+        || format!("invalid ({} bytes)", bytes.len()),
+        |value| value.to_string(),
+    )
+}
+
+/// Formats `lamports` in SOL, exactly
+fn sol(lamports: u64) -> String {
+    format!(
+        "{}.{:09} SOL",
+        lamports / LAMPORTS_PER_SOL,
+        lamports % LAMPORTS_PER_SOL
+    )
+}
+
+fn convert(input: &Path, output: &Path, encode_args: &EncodeArgs) -> Result<(), Box<dyn Error>> {
+    let snapshot = open_snapshot(input)?;
+    let signer = encode_args.signer()?;
+    write_output(output, |writer| {
+        reencode_snapshot(
+            snapshot,
+            &encode_args.sections(),
+            encode_args.encoding(),
+            signer.as_ref(),
+            writer,
+        )
+        .map_err(Into::into)
+    })?;
+    println!("wrote {}", output.display());
+    Ok(())
+}
+
+fn convert_archive(
+    full_snapshot_archive: &Path,
+    output: &Path,
+    archive_args: &ArchiveArgs,
+    encode_args: &EncodeArgs,
+) -> Result<(), Box<dyn Error>> {
+    let genesis_config = archive_args.genesis_config()?;
+    let signer = encode_args.signer()?;
+    let slot = write_output(output, |writer| {
+        archive_args.with_work_dir(|work_dir| {
+            archive_to_snapshot(
+                full_snapshot_archive,
+                archive_args.incremental_archive.as_deref(),
+                &genesis_config,
+                work_dir,
+                encode_args.sections(),
+                encode_args.encoding(),
+                signer.as_ref(),
+                writer,
+            )
+        })
+    })?;
+    println!("wrote a snapshot of slot {slot} to {}", output.display());
+    Ok(())
+}
+
+fn convert_to_archive(
+    input: &Path,
+    archives_dir: &Path,
+    archive_args: &ArchiveArgs,
+) -> Result<(), Box<dyn Error>> {
+    let genesis_config = archive_args.genesis_config()?;
+    let snapshot = open_snapshot(input)?;
+    let full_snapshot = archive_args
+        .full_snapshot
+        .as_deref()
+        .map(open_snapshot)
+        .transpose()?;
+    fs::create_dir_all(archives_dir)?;
+    let archive_info = archive_args.with_work_dir(|work_dir| match full_snapshot {
+        Some(full_snapshot) => incremental_snapshot_to_archive(
+            full_snapshot,
+            snapshot,
+            &genesis_config,
+            work_dir,
+            archives_dir,
+            ArchiveFormat::TarZstd,
+        ),
+        None => snapshot_to_archive(
+            snapshot,
+            &genesis_config,
+            work_dir,
+            archives_dir,
+            ArchiveFormat::TarZstd,
+        ),
+    })?;
+    println!("wrote {}", archive_info.path().display());
+    Ok(())
+}
+
+fn verify(
+    path: &Path,
+    trusted_signer: Option<&Pubkey>,
+    full_snapshot_path: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let snapshot = open_snapshot(path)?;
+    let mut sections = match trusted_signer {
+        Some(trusted_signer) => verify_signature(snapshot, trusted_signer)?,
+        None => verify_checksums(snapshot)?,
+    };
+    let snapshot_fields = decode_snapshot(&mut sections)?;
+    let num_account_records = count_account_records(&snapshot_fields, sections)?;
+    let slot = snapshot_fields.bank.slot;
+    if let Some(full_snapshot_path) = full_snapshot_path {
+        let full_snapshot = open_snapshot(full_snapshot_path)?;
+        let full_snapshot_fields = decode_snapshot(&mut verify_checksums(full_snapshot)?)?;
+        merge_incremental_snapshot(full_snapshot_fields, snapshot_fields)?;
+    }
+    println!("ok: slot {slot}, {num_account_records} account records");
+    Ok(())
+}
+
+fn diff(a_path: &Path, b_path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut a_sections = verify_checksums(open_snapshot(a_path)?)?;
+    let mut b_sections = verify_checksums(open_snapshot(b_path)?)?;
+    let a = decode_snapshot(&mut a_sections)?;
+    let b = decode_snapshot(&mut b_sections)?;
+
+    let mut differences = Vec::new();
+    macro_rules! diff_fields {
+        ($prefix:literal, $a:expr, $b:expr, [$($field:ident),+ $(,)?]) => {
+            $(
+                if $a.$field != $b.$field {
+                    differences.push(concat!($prefix, stringify!($field)).to_string());
+                }
+            )+
+        };
+    }
+    diff_fields!(
+        "header.",
+        a.header,
+        b.header,
+        [version, solana_version, creation_time, sections]
+    );
+    if a.kind != b.kind {
+        differences.push("kind".to_string());
+    }
+    differences.extend(
+        diff_bank_fields(&a.bank, &b.bank)
+            .into_iter()
+            .map(|field| format!("bank.{field}")),
+    );
+    diff_fields!(
+        "accounts_db.",
+        a.accounts_db,
+        b.accounts_db,
+        [
+            slot,
+            write_version,
+            storage_entries,
+            accounts_delta_hash,
+            accounts_hash,
+            bank_hash_stats,
+        ]
+    );
+    if !slot_deltas_eq(&a.status_cache, &b.status_cache) {
+        differences.push("status_cache".to_string());
+    }
+
+    // Accounts are matched up by their pubkey and slot, since the same account can be stored in
+    // more than one slot
+    let mut b_account_records = HashMap::new();
+    if b.header.has_section(Section::Accounts) {
+        for account_record in AccountRecords::new(b_sections) {
+            let account_record = account_record?;
+            b_account_records.insert((account_record.pubkey, account_record.slot), account_record);
+        }
+    }
+    let (mut num_only_in_a, mut num_changed) = (0, 0);
+    if a.header.has_section(Section::Accounts) {
+        for account_record in AccountRecords::new(a_sections) {
+            let account_record: AccountRecord = account_record?;
+            match b_account_records.remove(&(account_record.pubkey, account_record.slot)) {
+                None => num_only_in_a += 1,
+                Some(b_account_record) if b_account_record != account_record => num_changed += 1,
+                Some(_) => {}
+            }
+        }
+    }
+    let num_only_in_b = b_account_records.len();
+    if num_only_in_a + num_only_in_b + num_changed > 0 {
+        differences.push(format!(
+            "accounts: {num_only_in_a} only in a, {num_only_in_b} only in b, {num_changed} changed"
+        ));
+    }
+
+    if differences.is_empty() {
+        println!("no differences");
+    }
+    for difference in differences {
+        println!("{difference}");
+    }
+    Ok(())
+}
+
+/// Returns whether `path` is named like a validator's full snapshot archive
+fn is_full_snapshot_archive(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|file_name| file_name.to_str())
+        .is_some_and(|file_name| {
+            snapshot_utils::parse_full_snapshot_archive_filename(file_name).is_ok()
+        })
+}
+
+fn is_incremental_snapshot_archive(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|file_name| file_name.to_str())
+        .is_some_and(|file_name| {
+            snapshot_utils::parse_incremental_snapshot_archive_filename(file_name).is_ok()
+        })
+}
+
+/// Calls `write` with a writer to a temporary file next to `output`, which is moved to `output`
+/// once `write` succeeds, so that a failed command leaves no partial output behind, and converting
+/// a snapshot onto itself does not truncate it before it is read
+fn write_output<T>(
+    output: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    let mut partial_output = output.as_os_str().to_owned();
+    partial_output.push(".partial");
+    let partial_output = PathBuf::from(partial_output);
+    let result = (|| {
+        let mut writer = BufWriter::new(File::create(&partial_output)?);
+        let value = write(&mut writer)?;
+        writer.flush()?;
+        Ok(value)
+    })();
+    if result.is_err() {
+        // NOTE: Report the write's error, rather than whether the partial file could be removed
+        let _ = fs::remove_file(&partial_output);
+    } else {
+        fs::rename(&partial_output, output)?;
+    }
+    result
+}
+
+fn print_stats(path: &Path) -> Result<(), Box<dyn Error>> {
+    let snapshot = open_snapshot(path)?;
+    let snapshot_len = snapshot.get_ref().metadata()?.len();
+    let field_sizes = stats::field_sizes(snapshot)?;
+    let path_width = field_sizes
+        .iter()
+        .map(|field_size| field_size.path.len())
+        .max()
+        .unwrap_or_default();
+    println!("{:path_width$} {:>12} {:>7}", "field", "bytes", "share");
+    for field_size in field_sizes {
+        println!(
+            "{:path_width$} {:>12} {:>6.2}%",
+            field_size.path,
+            field_size.len,
+            field_size.len as f64 / snapshot_len as f64 * 100.0,
+        );
+    }
+    println!("{:path_width$} {:>12}", "total", snapshot_len);
+    Ok(())
+}
+
+/// Opens a snapshot to be read as it is verified and decoded, rather than all at once
+fn open_snapshot(path: &Path) -> io::Result<BufReader<File>> {
+    File::open(path).map(BufReader::new)
+}
+
+/// Reads through the accounts section, if there is one, and returns how many records it has
+fn count_account_records(
+    snapshot_fields: &SnapshotFields,
+    sections: impl Read,
+) -> Result<usize, SnapshotDecodeError> {
+    if !snapshot_fields.header.has_section(Section::Accounts) {
+        return Ok(0);
+    }
+    AccountRecords::new(sections).try_fold(0, |num_account_records, account_record| {
+        account_record.map(|_| num_account_records + 1)
+    })
+}
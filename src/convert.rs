@@ -0,0 +1,294 @@
+use {
+    crate::{
+        account_records,
+        canonical::{self, Encoding},
+        check_bank_fields,
+        checksum::{verify_checksums, ChecksumWriter},
+        decode_snapshot,
+        error::{ArchiveError, ResultExt, SnapshotDecodeError},
+        header::SnapshotHeader,
+        merge::merge_incremental_snapshot,
+        read_required_message, rebuild_bank,
+        schema::solana::snapshot::{
+            header::Section, Header as ProtoHeader, Snapshot as ProtoSnapshot,
+        },
+        snapshot_bank, SnapshotFields, SnapshotKind,
+    },
+    prost::Message,
+    solana_runtime::{
+        accounts_db::{
+            AccountShrinkThreshold, AccountsDbConfig, AccountsHashVerificationError,
+            VerifyAccountsHashAndLamportsConfig,
+        },
+        accounts_index::AccountSecondaryIndexes,
+        bank::Bank,
+        runtime_config::RuntimeConfig,
+        snapshot_archive_info::{
+            FullSnapshotArchiveInfo, IncrementalSnapshotArchiveInfo, SnapshotArchiveInfoGetter,
+        },
+        snapshot_utils::{self, ArchiveFormat},
+    },
+    solana_sdk::{clock::Slot, feature_set, genesis_config::GenesisConfig, signature::Keypair},
+    std::{
+        collections::BTreeSet,
+        fs,
+        io::{self, Read, Seek, Write},
+        num::NonZeroUsize,
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+};
+
+/// Re-encodes a snapshot without rebuilding its bank, e.g. to make it canonical or to sign it
+///
+/// The snapshot's sections that are not in `sections` are dropped, and the rest are copied as they
+/// are.  Sections cannot be added, since that would need the bank.  The checksums are
+/// recalculated, so the snapshot is checked against its old ones first.
+pub fn reencode_snapshot(
+    snapshot: impl Read + Seek,
+    sections: &BTreeSet<Section>,
+    encoding: Encoding,
+    signer: Option<&Keypair>,
+    writer: &mut impl Write,
+) -> Result<(), SnapshotDecodeError> {
+    let mut reader = verify_checksums(snapshot)?;
+    let mut buf = Vec::new();
+    let header: ProtoHeader = read_required_message(&mut reader, &mut buf, "header")?;
+    let mut header = SnapshotHeader::try_from(header).within("header")?;
+    header.sections.retain(|section| sections.contains(section));
+    let mut proto_snapshot: ProtoSnapshot =
+        read_required_message(&mut reader, &mut buf, "snapshot")?;
+    if !header.has_section(Section::StatusCache) {
+        proto_snapshot.status_cache = None;
+    }
+    if encoding == Encoding::Canonical {
+        header.creation_time = 0;
+        if let Some(bank) = &mut proto_snapshot.bank {
+            canonical::canonicalize_bank(bank);
+        }
+    }
+
+    let mut writer = ChecksumWriter::new(writer);
+    writer.write_all(&ProtoHeader::from(&header).encode_length_delimited_to_vec())?;
+    writer.end_section("header");
+    writer.write_all(&proto_snapshot.encode_length_delimited_to_vec())?;
+    writer.end_section("snapshot");
+    if header.has_section(Section::Accounts) {
+        io::copy(&mut reader, &mut writer)?;
+        writer.end_section("accounts");
+    }
+    writer.finish(signer)?;
+    Ok(())
+}
+
+/// Rebuilds the bank from a validator's snapshot archives, then writes a full snapshot of it
+///
+/// The archives are unpacked into `work_dir`, which also holds the bank's storages, so it must be
+/// big enough for all the accounts.  The snapshot has `sections`.  Returns the bank's slot.
+#[allow(clippy::too_many_arguments)]
+pub fn archive_to_snapshot(
+    full_snapshot_archive: &Path,
+    incremental_snapshot_archive: Option<&Path>,
+    genesis_config: &GenesisConfig,
+    work_dir: &Path,
+    sections: BTreeSet<Section>,
+    encoding: Encoding,
+    signer: Option<&Keypair>,
+    writer: &mut impl Write,
+) -> Result<Slot, ArchiveError> {
+    let full_snapshot_archive_info =
+        FullSnapshotArchiveInfo::new_from_path(full_snapshot_archive.to_path_buf())?;
+    let incremental_snapshot_archive_info = incremental_snapshot_archive
+        .map(|path| IncrementalSnapshotArchiveInfo::new_from_path(path.to_path_buf()))
+        .transpose()?;
+    let (bank_snapshots_dir, account_run_dir) = create_work_dirs(work_dir)?;
+
+    let (bank, _timings) = snapshot_utils::bank_from_snapshot_archives(
+        &[account_run_dir],
+        &bank_snapshots_dir,
+        &full_snapshot_archive_info,
+        incremental_snapshot_archive_info.as_ref(),
+        genesis_config,
+        &RuntimeConfig::default(),
+        None,
+        None,
+        AccountSecondaryIndexes::default(),
+        None,
+        AccountShrinkThreshold::default(),
+        false,
+        false,
+        false,
+        false,
+        // NOTE: Skip the accounts hash check, which would run in the background and panic if the
+        // archive is bad, and run it below instead
+        Some(AccountsDbConfig {
+            skip_initial_hash_calc: true,
+            ..AccountsDbConfig::default()
+        }),
+        None,
+        &Arc::default(),
+    )?;
+    verify_accounts_hash(
+        &bank,
+        incremental_snapshot_archive_info
+            .is_some()
+            .then(|| full_snapshot_archive_info.slot()),
+    )?;
+
+    // NOTE: An incremental archive is applied onto its full archive, so the bank is always whole
+    snapshot_bank(
+        &bank,
+        &SnapshotKind::Full,
+        sections,
+        encoding,
+        signer,
+        writer,
+    )?;
+    Ok(bank.slot())
+}
+
+/// Checks the accounts in the bank's storages against the accounts hash and the capitalization,
+/// same as booting from the archives does, but in the foreground
+///
+/// If the bank was rebuilt from an incremental archive, `base_slot` is the full archive's slot.
+pub fn verify_accounts_hash(bank: &Bank, base_slot: Option<Slot>) -> Result<(), ArchiveError> {
+    let accounts_db = &bank.rc.accounts.accounts_db;
+    // NOTE: Same as the runtime, an incremental archive only has an incremental accounts hash if
+    // the feature is active, otherwise its accounts hash covers every account
+    let base = base_slot
+        .filter(|_| {
+            bank.feature_set.is_active(
+                &feature_set::incremental_snapshot_only_incremental_hash_calculation::id(),
+            )
+        })
+        .map(|base_slot| {
+            accounts_db
+                .get_accounts_hash(base_slot)
+                .map(|(_accounts_hash, base_capitalization)| (base_slot, base_capitalization))
+                .ok_or(ArchiveError::AccountsHash(
+                    AccountsHashVerificationError::MissingAccountsHash,
+                ))
+        })
+        .transpose()?;
+    accounts_db
+        .verify_accounts_hash_and_lamports(
+            bank.slot(),
+            bank.capitalization(),
+            base,
+            VerifyAccountsHashAndLamportsConfig {
+                ancestors: &bank.ancestors,
+                test_hash_calculation: false,
+                epoch_schedule: bank.epoch_schedule(),
+                rent_collector: bank.rent_collector(),
+                ignore_mismatch: false,
+                store_detailed_debug_info: false,
+                use_bg_thread_pool: false,
+                include_slot_in_hash: bank.include_slot_in_hash(),
+            },
+        )
+        .map_err(ArchiveError::AccountsHash)
+}
+
+/// Rebuilds the bank from a full snapshot, then writes a validator's full snapshot archive of it
+/// into `archives_dir`, so that a node that does not understand snapshots can boot from it
+///
+/// The bank is rebuilt with `genesis_config`, which must be the one of the snapshot's cluster.  The
+/// bank's storages are put in `work_dir`.  Returns the archive's info, e.g. its path.
+pub fn snapshot_to_archive(
+    snapshot: impl Read + Seek,
+    genesis_config: &GenesisConfig,
+    work_dir: &Path,
+    archives_dir: &Path,
+    archive_format: ArchiveFormat,
+) -> Result<FullSnapshotArchiveInfo, ArchiveError> {
+    let mut sections = verify_checksums(snapshot)?;
+    let snapshot_fields = decode_snapshot(&mut sections)?;
+    if snapshot_fields.kind != SnapshotKind::Full {
+        return Err(ArchiveError::NotFull);
+    }
+    check_convertible(&snapshot_fields, genesis_config)?;
+    let (bank_snapshots_dir, account_run_dir) = create_work_dirs(work_dir)?;
+    let accounts_db = account_records::rebuild_accounts_db(
+        &snapshot_fields.accounts_db,
+        sections,
+        vec![account_run_dir],
+        &genesis_config.cluster_type,
+    )?;
+    let bank = rebuild_bank(snapshot_fields, genesis_config, Arc::new(accounts_db))?;
+    write_full_archive(&bank, bank_snapshots_dir, archives_dir, archive_format)
+}
+
+/// Applies an incremental snapshot onto its full snapshot, rebuilds the bank from both, then writes
+/// a validator's full snapshot archive of it, same as `snapshot_to_archive()`
+pub fn incremental_snapshot_to_archive(
+    full_snapshot: impl Read + Seek,
+    incremental_snapshot: impl Read + Seek,
+    genesis_config: &GenesisConfig,
+    work_dir: &Path,
+    archives_dir: &Path,
+    archive_format: ArchiveFormat,
+) -> Result<FullSnapshotArchiveInfo, ArchiveError> {
+    let mut full_sections = verify_checksums(full_snapshot)?;
+    let full_snapshot_fields = decode_snapshot(&mut full_sections)?;
+    let mut incremental_sections = verify_checksums(incremental_snapshot)?;
+    let incremental_snapshot_fields = decode_snapshot(&mut incremental_sections)?;
+    if !full_snapshot_fields.header.has_section(Section::Accounts) {
+        return Err(ArchiveError::MissingAccounts);
+    }
+    let snapshot_fields =
+        merge_incremental_snapshot(full_snapshot_fields, incremental_snapshot_fields)?;
+    check_convertible(&snapshot_fields, genesis_config)?;
+    let (bank_snapshots_dir, account_run_dir) = create_work_dirs(work_dir)?;
+    let accounts_db = account_records::rebuild_merged_accounts_db(
+        &snapshot_fields,
+        full_sections,
+        incremental_sections,
+        vec![account_run_dir],
+        &genesis_config.cluster_type,
+    )?;
+    let bank = rebuild_bank(snapshot_fields, genesis_config, Arc::new(accounts_db))?;
+    write_full_archive(&bank, bank_snapshots_dir, archives_dir, archive_format)
+}
+
+/// Checks that a snapshot can be converted into a snapshot archive with `genesis_config`, before
+/// anything is written to the work dir
+fn check_convertible(
+    snapshot_fields: &SnapshotFields,
+    genesis_config: &GenesisConfig,
+) -> Result<(), ArchiveError> {
+    if !snapshot_fields.header.has_section(Section::Accounts) {
+        return Err(ArchiveError::MissingAccounts);
+    }
+    check_bank_fields(&snapshot_fields.bank, genesis_config).within("bank")?;
+    Ok(())
+}
+
+/// Writes a validator's full snapshot archive of a rebuilt bank into `archives_dir`
+fn write_full_archive(
+    bank: &Bank,
+    bank_snapshots_dir: PathBuf,
+    archives_dir: &Path,
+    archive_format: ArchiveFormat,
+) -> Result<FullSnapshotArchiveInfo, ArchiveError> {
+    // NOTE: Retain every archive, so that none already in `archives_dir` are purged
+    let archive_info = snapshot_utils::bank_to_full_snapshot_archive(
+        bank_snapshots_dir,
+        bank,
+        None,
+        archives_dir,
+        archives_dir,
+        archive_format,
+        NonZeroUsize::MAX,
+        NonZeroUsize::MAX,
+    )?;
+    Ok(archive_info)
+}
+
+/// Creates the bank snapshots dir and the accounts run dir inside `work_dir`, and returns them
+fn create_work_dirs(work_dir: &Path) -> Result<(PathBuf, PathBuf), ArchiveError> {
+    let bank_snapshots_dir = work_dir.join("bank_snapshots");
+    fs::create_dir_all(&bank_snapshots_dir)?;
+    let (account_run_dir, _account_snapshot_dir) =
+        snapshot_utils::create_accounts_run_and_snapshot_dirs(work_dir.join("accounts"))?;
+    Ok((bank_snapshots_dir, account_run_dir))
+}
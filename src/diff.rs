@@ -0,0 +1,109 @@
+use {
+    solana_runtime::{
+        bank::{BankFieldsToDeserialize, BankSlotDelta},
+        epoch_stakes::EpochStakes,
+    },
+    solana_sdk::clock::Epoch,
+    std::collections::HashMap,
+};
+
+/// Compares two sets of slot deltas, regardless of their order
+pub fn slot_deltas_eq(a: &[BankSlotDelta], b: &[BankSlotDelta]) -> bool {
+    // The statuses are behind mutexes, so copy them out to compare
+    let by_slot = |slot_deltas: &[BankSlotDelta]| {
+        slot_deltas
+            .iter()
+            .map(|(slot, is_root, status)| (*slot, (*is_root, status.lock().unwrap().clone())))
+            .collect::<HashMap<_, _>>()
+    };
+    a.len() == b.len() && by_slot(a) == by_slot(b)
+}
+
+/// Compares two sets of epoch stakes, regardless of the order of each node's vote accounts
+///
+/// NOTE: That order comes from hash map iteration when the epoch stakes are built from their stakes,
+/// so it differs between a bank and the same bank rebuilt.
+pub fn epoch_stakes_eq(a: &HashMap<Epoch, EpochStakes>, b: &HashMap<Epoch, EpochStakes>) -> bool {
+    let sorted_node_vote_accounts = |epoch_stakes: &EpochStakes| {
+        epoch_stakes
+            .node_id_to_vote_accounts()
+            .iter()
+            .map(|(node_id, node_vote_accounts)| {
+                let mut vote_accounts = node_vote_accounts.vote_accounts.clone();
+                vote_accounts.sort_unstable();
+                (*node_id, (vote_accounts, node_vote_accounts.total_stake))
+            })
+            .collect::<HashMap<_, _>>()
+    };
+    a.len() == b.len()
+        && a.iter().all(|(epoch, a)| {
+            b.get(epoch).is_some_and(|b| {
+                a.stakes() == b.stakes()
+                    && a.total_stake() == b.total_stake()
+                    && a.epoch_authorized_voters() == b.epoch_authorized_voters()
+                    && sorted_node_vote_accounts(a) == sorted_node_vote_accounts(b)
+            })
+        })
+}
+
+/// Returns the names of all the fields that differ between `a` and `b`
+pub fn diff_bank_fields(
+    a: &BankFieldsToDeserialize,
+    b: &BankFieldsToDeserialize,
+) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    macro_rules! diff_fields {
+        (@eq $a:ident, $b:expr) => {
+            $a == $b
+        };
+        (@eq $a:ident, $b:expr, $eq:ident) => {
+            $eq($a, $b)
+        };
+        ($($field:ident $(by $eq:ident)?),+ $(,)?) => {{
+            // Destructure, so that new fields fail to compile until they are compared too
+            let BankFieldsToDeserialize { $($field),+ } = a;
+            $(
+                if !diff_fields!(@eq $field, &b.$field $(, $eq)?) {
+                    fields.push(stringify!($field));
+                }
+            )+
+        }};
+    }
+    diff_fields!(
+        blockhash_queue,
+        ancestors,
+        hash,
+        parent_hash,
+        parent_slot,
+        hard_forks,
+        transaction_count,
+        tick_height,
+        signature_count,
+        capitalization,
+        max_tick_height,
+        hashes_per_tick,
+        ticks_per_slot,
+        ns_per_slot,
+        genesis_creation_time,
+        slots_per_year,
+        slot,
+        epoch,
+        block_height,
+        collector_id,
+        collector_fees,
+        fee_calculator,
+        fee_rate_governor,
+        collected_rent,
+        rent_collector,
+        epoch_schedule,
+        inflation,
+        stakes,
+        epoch_stakes by epoch_stakes_eq,
+        is_delta,
+        accounts_data_len,
+        incremental_snapshot_persistence,
+        epoch_accounts_hash,
+        epoch_reward_status,
+    );
+    fields
+}
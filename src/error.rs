@@ -0,0 +1,292 @@
+use {
+    solana_runtime::{
+        accounts_db::{AccountsHashVerificationError, AppendVecId},
+        snapshot_utils::SnapshotError,
+    },
+    solana_sdk::{clock::Slot, pubkey::Pubkey},
+    std::{
+        fmt::{self, Display},
+        io,
+    },
+    thiserror::Error,
+};
+
+/// Errors from converting a bank into its snapshot schema
+#[derive(Error, Debug)]
+pub enum SnapshotEncodeError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("{path}: value {value} does not fit in {target}")]
+    OutOfRange {
+        path: FieldPath,
+        value: String,
+        target: &'static str,
+    },
+
+    #[error("{path}: missing state: {reason}")]
+    MissingState { path: FieldPath, reason: String },
+
+    #[error("{path}: invalid value: {reason}")]
+    InvalidValue { path: FieldPath, reason: String },
+}
+
+/// Errors from converting a snapshot schema back into a bank
+#[derive(Error, Debug)]
+pub enum SnapshotDecodeError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("malformed protobuf: {0}")]
+    Protobuf(#[from] prost::DecodeError),
+
+    #[error("no checksums at the end of the snapshot, it may be truncated")]
+    MissingChecksums,
+
+    #[error("{section}: checksum does not match, the snapshot is corrupt")]
+    ChecksumMismatch { section: String },
+
+    #[error("snapshot is not signed")]
+    MissingSignature,
+
+    #[error("snapshot is signed by {signer}, which is not trusted")]
+    UntrustedSigner { signer: Pubkey },
+
+    #[error("snapshot signature is invalid")]
+    InvalidSignature,
+
+    #[error("unsupported snapshot version {version}, this build only reads version {supported}")]
+    UnsupportedVersion { version: u32, supported: u32 },
+
+    #[error("{path}: missing required field")]
+    MissingField { path: FieldPath },
+
+    #[error("{path}: expected {expected} bytes, got {actual}")]
+    InvalidLength {
+        path: FieldPath,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("{path}: value {value} does not fit in {target}")]
+    OutOfRange {
+        path: FieldPath,
+        value: String,
+        target: &'static str,
+    },
+
+    #[error("{path}: invalid value: {reason}")]
+    InvalidValue { path: FieldPath, reason: String },
+
+    #[error("{path}: duplicate entry for {key}")]
+    DuplicateKey { path: FieldPath, key: String },
+
+    #[error("{path}: invalid vote account: {reason}")]
+    InvalidVoteAccount { path: FieldPath, reason: String },
+}
+
+/// Errors from applying an incremental snapshot onto a full snapshot
+#[derive(Error, Debug)]
+pub enum SnapshotMergeError {
+    #[error("the base snapshot is not a full snapshot")]
+    NotFull,
+
+    #[error("the snapshot to apply is not an incremental snapshot")]
+    NotIncremental,
+
+    #[error("incremental snapshot is based on slot {base_slot}, not the full snapshot's slot {full_slot}")]
+    BaseSlotMismatch { base_slot: Slot, full_slot: Slot },
+
+    #[error("incremental snapshot's base hash does not match the full snapshot's accounts hash")]
+    BaseHashMismatch,
+
+    #[error("incremental snapshot has no incremental snapshot persistence")]
+    MissingPersistence,
+
+    #[error("incremental snapshot persistence: {field} does not match the full snapshot")]
+    PersistenceMismatch { field: &'static str },
+
+    #[error(
+        "incremental snapshot has a storage for slot {slot}, not after its base slot {base_slot}"
+    )]
+    StaleStorage { slot: Slot, base_slot: Slot },
+
+    #[error("storage id {id} is in both snapshots")]
+    DuplicateStorageId { id: AppendVecId },
+}
+
+/// Errors from converting between Solana's snapshot archives and snapshots
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("snapshot archive: {0}")]
+    Archive(#[from] SnapshotError),
+
+    #[error("encode: {0}")]
+    Encode(#[from] SnapshotEncodeError),
+
+    #[error("decode: {0}")]
+    Decode(#[from] SnapshotDecodeError),
+
+    #[error("merge: {0}")]
+    Merge(#[from] SnapshotMergeError),
+
+    #[error("snapshot archive: accounts do not match the accounts hash: {0:?}")]
+    AccountsHash(AccountsHashVerificationError),
+
+    #[error("an incremental snapshot can only be converted into a snapshot archive with its full snapshot")]
+    NotFull,
+
+    #[error("snapshot has no accounts section to convert")]
+    MissingAccounts,
+}
+
+impl SnapshotEncodeError {
+    pub fn out_of_range(field: impl Display, value: impl Display, target: &'static str) -> Self {
+        Self::OutOfRange {
+            path: FieldPath::new(field),
+            value: value.to_string(),
+            target,
+        }
+    }
+
+    pub fn missing_state(field: impl Display, reason: impl Display) -> Self {
+        Self::MissingState {
+            path: FieldPath::new(field),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn invalid_value(field: impl Display, reason: impl Display) -> Self {
+        Self::InvalidValue {
+            path: FieldPath::new(field),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl SnapshotDecodeError {
+    pub fn missing_field(field: impl Display) -> Self {
+        Self::MissingField {
+            path: FieldPath::new(field),
+        }
+    }
+
+    pub fn invalid_length(field: impl Display, expected: usize, actual: usize) -> Self {
+        Self::InvalidLength {
+            path: FieldPath::new(field),
+            expected,
+            actual,
+        }
+    }
+
+    pub fn out_of_range(field: impl Display, value: impl Display, target: &'static str) -> Self {
+        Self::OutOfRange {
+            path: FieldPath::new(field),
+            value: value.to_string(),
+            target,
+        }
+    }
+
+    pub fn invalid_value(field: impl Display, reason: impl Display) -> Self {
+        Self::InvalidValue {
+            path: FieldPath::new(field),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn duplicate_key(field: impl Display, key: impl Display) -> Self {
+        Self::DuplicateKey {
+            path: FieldPath::new(field),
+            key: key.to_string(),
+        }
+    }
+
+    pub fn invalid_vote_account(field: impl Display, reason: impl Display) -> Self {
+        Self::InvalidVoteAccount {
+            path: FieldPath::new(field),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// The path to a field within the snapshot, e.g. `bank.stakes.vote_accounts[3].pubkey`
+///
+/// Errors are created with the innermost field, and each enclosing message prepends its own field
+/// as the error propagates outwards.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FieldPath(Vec<String>);
+
+impl FieldPath {
+    pub fn new(field: impl Display) -> Self {
+        Self(vec![field.to_string()])
+    }
+
+    fn prepend(&mut self, field: impl Display) {
+        self.0.insert(0, field.to_string());
+    }
+}
+
+impl Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join("."))
+    }
+}
+
+/// Errors that know the path of the field they occurred in
+pub trait FieldError: Sized {
+    fn path_mut(&mut self) -> Option<&mut FieldPath>;
+
+    /// Prepends `field` to this error's path
+    fn within(mut self, field: impl Display) -> Self {
+        if let Some(path) = self.path_mut() {
+            path.prepend(field);
+        }
+        self
+    }
+}
+
+impl FieldError for SnapshotEncodeError {
+    fn path_mut(&mut self) -> Option<&mut FieldPath> {
+        match self {
+            Self::Io(_) => None,
+            Self::OutOfRange { path, .. }
+            | Self::MissingState { path, .. }
+            | Self::InvalidValue { path, .. } => Some(path),
+        }
+    }
+}
+
+impl FieldError for SnapshotDecodeError {
+    fn path_mut(&mut self) -> Option<&mut FieldPath> {
+        match self {
+            Self::Io(_)
+            | Self::Protobuf(_)
+            | Self::MissingChecksums
+            | Self::ChecksumMismatch { .. }
+            | Self::MissingSignature
+            | Self::UntrustedSigner { .. }
+            | Self::InvalidSignature
+            | Self::UnsupportedVersion { .. } => None,
+            Self::MissingField { path }
+            | Self::InvalidLength { path, .. }
+            | Self::OutOfRange { path, .. }
+            | Self::InvalidValue { path, .. }
+            | Self::DuplicateKey { path, .. }
+            | Self::InvalidVoteAccount { path, .. } => Some(path),
+        }
+    }
+}
+
+/// Adds field context to the error of a `Result`
+pub trait ResultExt<T, E> {
+    fn within(self, field: impl Display) -> Result<T, E>;
+}
+
+impl<T, E: FieldError> ResultExt<T, E> for Result<T, E> {
+    fn within(self, field: impl Display) -> Result<T, E> {
+        self.map_err(|err| err.within(field))
+    }
+}
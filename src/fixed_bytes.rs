@@ -0,0 +1,80 @@
+use {
+    crate::error::SnapshotDecodeError,
+    solana_runtime::{
+        accounts_hash::{AccountsDeltaHash, AccountsHash},
+        serde_snapshot::{SerdeAccountsHash, SerdeIncrementalAccountsHash},
+    },
+    solana_sdk::{
+        hash::{Hash, HASH_BYTES},
+        pubkey::{Pubkey, PUBKEY_BYTES},
+        signature::{Signature, SIGNATURE_BYTES},
+    },
+    std::fmt::Display,
+};
+
+/// A runtime type that the schema stores as a fixed-width `bytes` field, e.g. a pubkey or a hash
+pub trait FixedBytes: Sized {
+    /// The number of bytes the field must have
+    const LEN: usize;
+
+    /// Converts from the field's bytes, or returns `None` if there are not exactly `LEN` of them
+    fn from_fixed_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+/// Decodes a fixed-width `bytes` field, or fails with the field's name if its length is wrong
+///
+/// All pubkeys and hashes are decoded with this, instead of being cast from their bytes.
+pub fn decode_bytes<T: FixedBytes>(
+    bytes: &[u8],
+    field: impl Display,
+) -> Result<T, SnapshotDecodeError> {
+    T::from_fixed_bytes(bytes)
+        .ok_or_else(|| SnapshotDecodeError::invalid_length(field, T::LEN, bytes.len()))
+}
+
+impl<const N: usize> FixedBytes for [u8; N] {
+    const LEN: usize = N;
+    fn from_fixed_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok()
+    }
+}
+
+impl FixedBytes for Pubkey {
+    const LEN: usize = PUBKEY_BYTES;
+    fn from_fixed_bytes(bytes: &[u8]) -> Option<Self> {
+        <[u8; PUBKEY_BYTES]>::from_fixed_bytes(bytes).map(Pubkey::from)
+    }
+}
+
+impl FixedBytes for Hash {
+    const LEN: usize = HASH_BYTES;
+    fn from_fixed_bytes(bytes: &[u8]) -> Option<Self> {
+        <[u8; HASH_BYTES]>::from_fixed_bytes(bytes).map(Hash::new_from_array)
+    }
+}
+
+impl FixedBytes for Signature {
+    const LEN: usize = SIGNATURE_BYTES;
+    fn from_fixed_bytes(bytes: &[u8]) -> Option<Self> {
+        <[u8; SIGNATURE_BYTES]>::from_fixed_bytes(bytes).map(Signature::from)
+    }
+}
+
+/// Implements `FixedBytes` for the runtime's newtypes around `Hash`
+macro_rules! impl_fixed_bytes_for_hash_newtypes {
+    ($($hash_newtype:ident),+ $(,)?) => {$(
+        impl FixedBytes for $hash_newtype {
+            const LEN: usize = HASH_BYTES;
+            fn from_fixed_bytes(bytes: &[u8]) -> Option<Self> {
+                Hash::from_fixed_bytes(bytes).map($hash_newtype)
+            }
+        }
+    )+};
+}
+
+impl_fixed_bytes_for_hash_newtypes!(
+    AccountsHash,
+    AccountsDeltaHash,
+    SerdeAccountsHash,
+    SerdeIncrementalAccountsHash,
+);
@@ -0,0 +1,101 @@
+use {
+    crate::{
+        error::SnapshotDecodeError,
+        schema::solana::snapshot::{header::Section, Header as ProtoHeader},
+    },
+    solana_sdk::clock::UnixTimestamp,
+    std::{collections::BTreeSet, time::SystemTime},
+};
+
+/// The version of the schema that this build writes
+///
+/// Bump this whenever a change to the schema would be misread by older builds.
+///
+/// - 1: initial version
+/// - 2: adds `Snapshot.kind`
+/// - 3: adds the checksums at the end of the file
+/// - 4: adds `AccountsDb.include_slot_in_hash`
+/// - 5: adds `Section::Unspecified` as the zero value, renumbering the sections
+pub const SCHEMA_VERSION: u32 = 5;
+
+/// Every optional section, which snapshots have unless told to leave some out
+pub const ALL_SECTIONS: [Section; 2] = [Section::Accounts, Section::StatusCache];
+
+/// The header at the start of every snapshot file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotHeader {
+    pub version: u32,
+    pub solana_version: String,
+    pub creation_time: UnixTimestamp,
+    pub sections: BTreeSet<Section>,
+}
+
+impl SnapshotHeader {
+    /// Returns the header for a snapshot taken now, by this build, which has `sections`
+    pub fn now(sections: BTreeSet<Section>) -> Self {
+        let creation_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as UnixTimestamp)
+            .unwrap_or_default();
+        Self {
+            version: SCHEMA_VERSION,
+            solana_version: solana_version::semver!().to_string(),
+            creation_time,
+            sections,
+        }
+    }
+
+    pub fn has_section(&self, section: Section) -> bool {
+        self.sections.contains(&section)
+    }
+}
+
+impl From<&SnapshotHeader> for ProtoHeader {
+    fn from(header: &SnapshotHeader) -> Self {
+        Self {
+            version: header.version,
+            solana_version: header.solana_version.clone(),
+            creation_time: header.creation_time,
+            sections: header
+                .sections
+                .iter()
+                .map(|section| i32::from(*section))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<ProtoHeader> for SnapshotHeader {
+    type Error = SnapshotDecodeError;
+    fn try_from(header: ProtoHeader) -> Result<Self, Self::Error> {
+        // NOTE: Older versions are not migrated, so their snapshots must be taken again
+        if header.version != SCHEMA_VERSION {
+            return Err(SnapshotDecodeError::UnsupportedVersion {
+                version: header.version,
+                supported: SCHEMA_VERSION,
+            });
+        }
+        let sections = header
+            .sections
+            .iter()
+            .enumerate()
+            .map(|(i, section)| match Section::from_i32(*section) {
+                Some(Section::Unspecified) => Err(SnapshotDecodeError::invalid_value(
+                    format_args!("sections[{i}]"),
+                    "unspecified section",
+                )),
+                Some(section) => Ok(section),
+                None => Err(SnapshotDecodeError::invalid_value(
+                    format_args!("sections[{i}]"),
+                    format_args!("unknown section {section}, which needs a newer protosnap"),
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            version: header.version,
+            solana_version: header.solana_version,
+            creation_time: header.creation_time,
+            sections,
+        })
+    }
+}
@@ -1,14 +1,43 @@
+mod account_records;
+mod canonical;
+mod checksum;
+mod cli;
+mod convert;
+mod diff;
+mod error;
+mod fixed_bytes;
+mod header;
+mod merge;
+mod signing;
+mod stats;
+mod test_bank;
+#[cfg(test)]
+mod tests;
+
 pub mod schema {
     pub mod solana {
         pub mod snapshot {
+            // The `Snapshot` message's nested types are generated into a `snapshot` module
+            #![allow(clippy::module_inception)]
             include!(concat!(env!("OUT_DIR"), "/solana.snapshot.rs"));
         }
     }
 }
 
 use {
+    canonical::Encoding,
+    checksum::ChecksumWriter,
+    clap::Parser,
+    cli::Cli,
+    error::{FieldError, ResultExt, SnapshotDecodeError, SnapshotEncodeError},
+    fixed_bytes::decode_bytes,
+    header::SnapshotHeader,
     prost::Message,
     schema::solana::snapshot::{
+        accounts_db::{
+            BankHashInfo as ProtoBankHashInfo, BankHashStats as ProtoBankHashStats,
+            StorageEntry as ProtoStorageEntry,
+        },
         blockhash_queue::Age as ProtoBlockhashAge,
         epoch_rewards::{
             epoch_stake_reward::{
@@ -21,84 +50,347 @@ use {
             EpochAuthorizedVoter as ProtoEpochAuthorizedVoter,
             NodeIdToVoteAccounts as ProtoNodeIdToVoteAccounts,
         },
+        header::Section,
+        snapshot::{
+            Full as ProtoSnapshotFull, Incremental as ProtoSnapshotIncremental,
+            Kind as ProtoSnapshotKind,
+        },
         stakes::{
             stake_delegations_entry::StakeDelegation as ProtoStakeDelegation,
             StakeDelegationsEntry as ProtoStakeDelegationsEntry, StakeHistory as ProtoStakeHistory,
             VoteAccountsEntry as ProtoVoteAccountsEntry,
         },
-        Account as ProtoAccount, Bank as ProtoBank, BlockhashQueue as ProtoBlockhashQueue,
+        status_cache::{
+            KeyStatus as ProtoKeyStatus, SlotDelta as ProtoSlotDelta, Status as ProtoStatus,
+        },
+        Account as ProtoAccount, AccountsDb as ProtoAccountsDb, Ancestor as ProtoAncestor,
+        Bank as ProtoBank, BlockhashQueue as ProtoBlockhashQueue,
         EpochRewards as ProtoEpochRewards, EpochSchedule as ProtoEpochSchedule,
         EpochStake as ProtoEpochStake, FeeCalculator as ProtoFeeCalculator,
-        FeeRateGovernor as ProtoFeeRateGovernor, HardFork as ProtoHardFork,
+        FeeRateGovernor as ProtoFeeRateGovernor, HardFork as ProtoHardFork, Header as ProtoHeader,
         IncrementalSnapshotPersistence as ProtoIncrementalSnapshotPersistence,
         Inflation as ProtoInflation, Rent as ProtoRent, RentCollector as ProtoRentCollector,
-        Snapshot as ProtoSnapshot, Stakes as ProtoStakes,
+        Snapshot as ProtoSnapshot, Stakes as ProtoStakes, StatusCache as ProtoStatusCache,
+        Uint128 as ProtoUint128,
     },
     solana_runtime::{
+        accounts::Accounts,
+        accounts_db::{
+            AccountsDb, AppendVecId, BankHashStats, CalcAccountsHashDataSource, IncludeSlotInHash,
+        },
+        accounts_hash::{
+            AccountsDeltaHash, AccountsHash, CalcAccountsHashConfig, HashStats,
+            IncrementalAccountsHash,
+        },
         bank::{
-            Bank, BankFieldsToDeserialize, EpochRewardStatus, StakeReward,
-            StartBlockHeightAndRewards,
+            Bank, BankFieldsToDeserialize, BankRc, BankSlotDelta, BankStatusCache,
+            EpochRewardStatus, RewardInfo, StakeReward, StartBlockHeightAndRewards,
         },
         blockhash_queue::{BlockhashQueue, HashAge as BlockhashAge},
-        epoch_stakes::NodeVoteAccounts,
+        epoch_stakes::{EpochStakes, NodeVoteAccounts},
         rent_collector::RentCollector,
+        runtime_config::RuntimeConfig,
         serde_snapshot::BankIncrementalSnapshotPersistence,
+        sorted_storages::SortedStorages,
+        stake_history::StakeHistory,
         stakes::{Stakes, StakesCache, StakesEnum},
+        vote_account::VoteAccount,
     },
     solana_sdk::{
-        account::{AccountSharedData, ReadableAccount},
-        clock::Slot,
+        account::{Account, AccountSharedData, ReadableAccount},
+        clock::{Epoch, Slot},
         epoch_schedule::EpochSchedule,
         fee_calculator::{FeeCalculator, FeeRateGovernor},
         genesis_config::GenesisConfig,
+        hard_forks::HardForks,
         hash::Hash,
         inflation::Inflation,
         pubkey::Pubkey,
         rent::Rent,
+        reward_type::RewardType,
+        signature::Keypair,
         stake::state::Delegation,
+        stake_history::StakeHistoryEntry,
+        timing::years_as_slots,
+        transaction::Result as TransactionResult,
+    },
+    std::{
+        collections::{BTreeSet, HashMap, HashSet},
+        fmt::Display,
+        io::{self, Read, Write},
+        ops::Deref,
+        process::ExitCode,
+        sync::{atomic::Ordering, Arc, Mutex},
+        time::Duration,
     },
-    std::{collections::HashMap, ops::Deref, sync::Arc},
 };
 
-fn main() {
-    let bank = {
-        let genesis_config = GenesisConfig::default();
-        let mut bank = Arc::new(Bank::new_for_tests(&genesis_config));
-        for _ in 0..21 {
-            bank = Arc::new(Bank::new_from_parent(
-                &bank,
-                &Pubkey::new_unique(),
-                bank.slot() + 1,
+fn main() -> ExitCode {
+    match cli::run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Writes a snapshot of `bank` to `writer`
+///
+/// The snapshot is written as the length-delimited `Header` and `Snapshot` messages, followed by
+/// the accounts section if `sections` has it, and ends with the checksums of each of those.  The
+/// status cache is only written if `sections` has it.  An incremental snapshot only has the
+/// accounts from storages newer than its base slot.  The accounts cache must be flushed first.  If
+/// `signer` is given, it signs the checksums.
+///
+/// NOTE: This stores hashes in the bank's accounts db, same as a validator taking a snapshot does.
+/// A full snapshot stores the bank's accounts hash if it was not calculated yet, and an incremental
+/// snapshot stores its incremental accounts hash.  See `calculate_accounts_hash()` and
+/// `calculate_incremental_accounts_hash()`.
+fn snapshot_bank(
+    bank: &Bank,
+    kind: &SnapshotKind,
+    sections: BTreeSet<Section>,
+    encoding: Encoding,
+    signer: Option<&Keypair>,
+    writer: &mut impl Write,
+) -> Result<(), SnapshotEncodeError> {
+    if let SnapshotKind::Incremental { base_slot, .. } = kind {
+        if *base_slot >= bank.slot() {
+            return Err(SnapshotEncodeError::invalid_value(
+                "kind.base_slot",
+                format_args!("must be older than the bank's slot {}", bank.slot()),
             ));
-            bank.fill_bank_with_ticks_for_tests();
         }
-        bank.freeze();
-        Arc::into_inner(bank).unwrap()
+    }
+    let mut header = SnapshotHeader::now(sections);
+    let mut proto_bank = ProtoBank::try_from(bank).within("bank")?;
+    if let SnapshotKind::Incremental {
+        base_slot,
+        base_hash,
+    } = kind
+    {
+        let incremental_snapshot_persistence =
+            incremental_snapshot_persistence(bank, *base_slot, base_hash)
+                .within("incremental_snapshot_persistence")
+                .within("bank")?;
+        proto_bank.incremental_snapshot_persistence =
+            Some((&incremental_snapshot_persistence).into());
+    }
+    if encoding == Encoding::Canonical {
+        header.creation_time = 0;
+        canonical::canonicalize_bank(&mut proto_bank);
+    }
+    let status_cache = header
+        .has_section(Section::StatusCache)
+        .then(|| ProtoStatusCache::try_from(bank.status_cache.read().unwrap().deref()))
+        .transpose()
+        .within("status_cache")?;
+    let snapshot = ProtoSnapshot {
+        bank: Some(proto_bank),
+        accounts_db: Some(
+            AccountsDbFields::new(bank, kind.base_slot())
+                .and_then(ProtoAccountsDb::try_from)
+                .within("accounts_db")?,
+        ),
+        status_cache,
+        kind: Some(kind.into()),
     };
+    let mut writer = ChecksumWriter::new(writer);
+    writer.write_all(&ProtoHeader::from(&header).encode_length_delimited_to_vec())?;
+    writer.end_section("header");
+    writer.write_all(&snapshot.encode_length_delimited_to_vec())?;
+    writer.end_section("snapshot");
+    if header.has_section(Section::Accounts) {
+        account_records::write_account_records(bank, kind.base_slot(), &mut writer)
+            .within("accounts")?;
+        writer.end_section("accounts");
+    }
+    writer.finish(signer)?;
+    Ok(())
+}
+
+/// Returns what an incremental snapshot of `bank` records about the full snapshot it is based on,
+/// and about itself, calculating the incremental accounts hash
+///
+/// The full snapshot's accounts hash must already be in the accounts db, as it is once the full
+/// snapshot has been taken.
+fn incremental_snapshot_persistence(
+    bank: &Bank,
+    base_slot: Slot,
+    base_hash: &AccountsHash,
+) -> Result<BankIncrementalSnapshotPersistence, SnapshotEncodeError> {
+    let accounts_db = &bank.rc.accounts.accounts_db;
+    let (full_hash, full_capitalization) =
+        accounts_db.get_accounts_hash(base_slot).ok_or_else(|| {
+            SnapshotEncodeError::missing_state(
+                "full_hash",
+                format_args!("no accounts hash for the base slot {base_slot}"),
+            )
+        })?;
+    if full_hash != *base_hash {
+        return Err(SnapshotEncodeError::invalid_value(
+            "full_hash",
+            format_args!(
+                "the accounts hash of the base slot is {}, not the base hash {}",
+                full_hash.0, base_hash.0,
+            ),
+        ));
+    }
+    // NOTE: The accounts db does not record which base slot an incremental accounts hash is for,
+    // so calculate it every time rather than reuse one that may be for another base slot
+    let (incremental_hash, incremental_capitalization) =
+        calculate_incremental_accounts_hash(bank, base_slot)?;
+    Ok(BankIncrementalSnapshotPersistence {
+        full_slot: base_slot,
+        full_hash: full_hash.into(),
+        full_capitalization,
+        incremental_hash: incremental_hash.into(),
+        incremental_capitalization,
+    })
+}
 
-    let serialized_snapshot = snapshot_bank(&bank);
-    println!(
-        "serialized snapshot: size: {}, {serialized_snapshot:?}",
-        serialized_snapshot.len()
+/// Rebuilds a bank from a decoded snapshot, with the genesis config of the snapshot's cluster
+///
+/// Snapshots do not carry the genesis config, so the caller must provide it, e.g. loaded from the
+/// cluster's ledger.  The bank is checked against it first.
+///
+/// NOTE: The accounts db must be provided by the caller, e.g. rebuilt from the snapshot's accounts
+/// section by `account_records::rebuild_accounts_db()`.
+fn rebuild_bank(
+    snapshot_fields: SnapshotFields,
+    genesis_config: &GenesisConfig,
+    accounts_db: Arc<AccountsDb>,
+) -> Result<Bank, SnapshotDecodeError> {
+    check_bank_fields(&snapshot_fields.bank, genesis_config).within("bank")?;
+    let bank = bank_from_fields(
+        snapshot_fields.bank,
+        genesis_config,
+        Accounts::new(accounts_db),
     );
+    bank.status_cache
+        .write()
+        .unwrap()
+        .append(&snapshot_fields.status_cache);
+    Ok(bank)
+}
+
+/// The decoded contents of a snapshot
+struct SnapshotFields {
+    header: SnapshotHeader,
+    kind: SnapshotKind,
+    bank: BankFieldsToDeserialize,
+    accounts_db: AccountsDbFields,
+    /// The root slot deltas of the status cache
+    status_cache: Vec<BankSlotDelta>,
+}
+
+/// Decodes the `Header` and `Snapshot` messages at the start of a snapshot into its fields
+///
+/// `reader` is left at the start of the sections that follow them.  The checksums must already be
+/// verified and stripped by `verify_checksums()`.
+fn decode_snapshot(reader: &mut impl Read) -> Result<SnapshotFields, SnapshotDecodeError> {
+    let mut buf = Vec::new();
+    // NOTE: Check the header before anything else, since it says how to read the rest
+    let header: ProtoHeader = read_required_message(reader, &mut buf, "header")?;
+    let header = SnapshotHeader::try_from(header).within("header")?;
+    let snapshot: ProtoSnapshot = read_required_message(reader, &mut buf, "snapshot")?;
+    decode_snapshot_message(header, snapshot)
+}
+
+/// Decodes a `Snapshot` message into its fields, given the snapshot's already decoded header
+fn decode_snapshot_message(
+    header: SnapshotHeader,
+    snapshot: ProtoSnapshot,
+) -> Result<SnapshotFields, SnapshotDecodeError> {
+    let status_cache = if header.has_section(Section::StatusCache) {
+        decode_required(snapshot.status_cache, "status_cache")?
+    } else {
+        Vec::new()
+    };
+    let kind = SnapshotKind::try_from(required(snapshot.kind, "kind")?).within("kind")?;
+    let bank = decode_bank(required(snapshot.bank, "bank")?).within("bank")?;
+    Ok(SnapshotFields {
+        header,
+        kind,
+        bank,
+        accounts_db: decode_required(snapshot.accounts_db, "accounts_db")?,
+        status_cache,
+    })
+}
+
+/// Whether a snapshot has every account, or only those changed since a full snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SnapshotKind {
+    Full,
+    Incremental {
+        base_slot: Slot,
+        /// The accounts hash of the full snapshot
+        base_hash: AccountsHash,
+    },
+}
+
+impl SnapshotKind {
+    /// Returns the slot of the full snapshot this is based on, if incremental
+    fn base_slot(&self) -> Option<Slot> {
+        match self {
+            Self::Full => None,
+            Self::Incremental { base_slot, .. } => Some(*base_slot),
+        }
+    }
+}
+
+impl From<&SnapshotKind> for ProtoSnapshotKind {
+    fn from(kind: &SnapshotKind) -> Self {
+        match kind {
+            SnapshotKind::Full => Self::Full(ProtoSnapshotFull {}),
+            SnapshotKind::Incremental {
+                base_slot,
+                base_hash,
+            } => Self::Incremental(ProtoSnapshotIncremental {
+                base_slot: *base_slot,
+                base_hash: base_hash.0.to_bytes().into(),
+            }),
+        }
+    }
+}
 
-    let deserialized_bank = rebuild_bank(serialized_snapshot);
+impl TryFrom<ProtoSnapshotKind> for SnapshotKind {
+    type Error = SnapshotDecodeError;
+    fn try_from(kind: ProtoSnapshotKind) -> Result<Self, Self::Error> {
+        match kind {
+            ProtoSnapshotKind::Full(ProtoSnapshotFull {}) => Ok(Self::Full),
+            ProtoSnapshotKind::Incremental(incremental) => Ok(Self::Incremental {
+                base_slot: incremental.base_slot,
+                base_hash: decode_bytes(&incremental.base_hash, "base_hash")
+                    .within("incremental")?,
+            }),
+        }
+    }
 }
 
-fn snapshot_bank(bank: &Bank) -> Vec<u8> {
-    let mut snapshot = ProtoSnapshot::default();
-    snapshot.bank = Some(ProtoBank::from(bank));
-    snapshot.encode_to_vec()
+/// The accounts db's part of a snapshot, i.e. what the bincode format stores in `AccountsDbFields`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AccountsDbFields {
+    slot: Slot,
+    write_version: u64,
+    storage_entries: Vec<StorageEntry>,
+    accounts_delta_hash: AccountsDeltaHash,
+    accounts_hash: AccountsHash,
+    bank_hash_stats: BankHashStats,
+    include_slot_in_hash: bool,
 }
 
-fn rebuild_bank(serialized_snapshot: Vec<u8>) -> Bank {
-    let snapshot: ProtoSnapshot = Message::decode(serialized_snapshot.as_slice()).unwrap();
-    let bank = snapshot.bank.unwrap();
-    bank.into()
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StorageEntry {
+    slot: Slot,
+    id: AppendVecId,
+    accounts_current_len: usize,
 }
 
-impl From<&Bank> for ProtoBank {
-    fn from(bank: &Bank) -> Self {
+impl TryFrom<&Bank> for ProtoBank {
+    type Error = SnapshotEncodeError;
+    fn try_from(bank: &Bank) -> Result<Self, Self::Error> {
         let ancestors_for_bank_fields = HashMap::<Slot, usize>::from(&bank.ancestors); // TODO: it would be nice to not make a copy
         let bank_fields = bank.get_fields_to_serialize(&ancestors_for_bank_fields);
         let hard_forks = bank_fields
@@ -106,12 +398,32 @@ impl From<&Bank> for ProtoBank {
             .read()
             .unwrap()
             .iter()
-            .map(|(slot, count)| ProtoHardFork {
-                slot: *slot,
-                count: *count as u64,
+            .map(|(slot, count)| -> Result<_, SnapshotEncodeError> {
+                Ok(ProtoHardFork {
+                    slot: *slot,
+                    count: encode_int(*count, "count")?,
+                })
             })
-            .collect();
-        let blockhash_queue = bank_fields.blockhash_queue.read().unwrap().deref().into();
+            .collect::<Result<_, _>>()
+            .within("hard_forks")?;
+        let ancestors = bank_fields
+            .ancestors
+            .iter()
+            .map(|(slot, value)| -> Result<_, SnapshotEncodeError> {
+                Ok(ProtoAncestor {
+                    slot: *slot,
+                    value: encode_int(*value, "value")?,
+                })
+            })
+            .collect::<Result<_, _>>()
+            .within("ancestors")?;
+        let blockhash_queue = bank_fields
+            .blockhash_queue
+            .read()
+            .unwrap()
+            .deref()
+            .try_into()
+            .within("blockhash_queue")?;
         let stakes = bank_fields.stakes.into();
         let epoch_stakes = bank_fields
             .epoch_stakes
@@ -155,37 +467,14 @@ impl From<&Bank> for ProtoBank {
                 }
             })
             .collect();
-        let epoch_rewards = bank
-            .get_epoch_reward_status_to_serialize()
-            .and_then(|epoch_reward_status| match epoch_reward_status {
-                EpochRewardStatus::Active(start_block_height_and_rewards) => {
-                    Some(start_block_height_and_rewards)
-                }
-                EpochRewardStatus::Inactive => None,
-            })
-            .map(|start_block_height_and_rewards| {
-                let to_proto_epoch_stake_reward =
-                    |stake_reward: &StakeReward| ProtoEpochStakeReward {
-                        stake_pubkey: stake_reward.stake_pubkey.to_bytes().into(),
-                        stake_account: Some(stake_reward.stake_account.clone().into()), // TODO: avoid the clone
-                        stake_reward_info: Some(ProtoEpochStakeRewardInfo {
-                            reward_kind: stake_reward.stake_reward_info.reward_type as i32, // TODO: change from cast to proper match
-                            lamports: stake_reward.stake_reward_info.lamports.try_into().unwrap(),
-                            post_balance: stake_reward.stake_reward_info.post_balance,
-                            commission: stake_reward.stake_reward_info.commission.map(Into::into),
-                        }),
-                    };
-                let epoch_stake_rewards = start_block_height_and_rewards
-                    .calculated_epoch_stake_rewards
-                    .iter()
-                    .map(to_proto_epoch_stake_reward)
-                    .collect();
-                ProtoEpochRewards {
-                    start_block_height: start_block_height_and_rewards.start_block_height,
-                    epoch_stake_rewards,
-                }
-            });
-        Self {
+        let epoch_rewards = match bank.get_epoch_reward_status_to_serialize() {
+            Some(EpochRewardStatus::Active(start_block_height_and_rewards)) => Some(
+                ProtoEpochRewards::try_from(start_block_height_and_rewards)
+                    .within("epoch_rewards")?,
+            ),
+            Some(EpochRewardStatus::Inactive) | None => None,
+        };
+        Ok(Self {
             epoch: bank_fields.epoch,
             block_height: bank_fields.block_height,
             slot: bank_fields.slot,
@@ -202,17 +491,14 @@ impl From<&Bank> for ProtoBank {
             max_tick_height: bank_fields.max_tick_height,
             hashes_per_tick: bank_fields.hashes_per_tick,
             ticks_per_slot: bank_fields.ticks_per_slot,
-            ns_per_slot: bank_fields
-                .ns_per_slot
-                .try_into()
-                .expect("cast ns_per_slot to u64"),
+            ns_per_slot: Some(bank_fields.ns_per_slot.into()),
             slots_per_year: bank_fields.slots_per_year,
             collector_id: bank_fields.collector_id.to_bytes().into(),
             collector_fees: bank_fields.collector_fees,
             collected_rent: bank_fields.collected_rent,
             accounts_data_size: bank_fields.accounts_data_len,
             is_delta: bank_fields.is_delta,
-            ancestors: bank.ancestors.keys(),
+            ancestors,
             genesis_creation_time: bank_fields.genesis_creation_time,
             inflation: Some(bank_fields.inflation.into()),
             hard_forks,
@@ -227,98 +513,474 @@ impl From<&Bank> for ProtoBank {
             stakes: Some(stakes),
             epoch_stakes,
             epoch_rewards,
+        })
+    }
+}
+
+fn bank_from_fields(
+    bank_fields: BankFieldsToDeserialize,
+    genesis_config: &GenesisConfig,
+    accounts: Accounts,
+) -> Bank {
+    let bank_rc = BankRc::new(accounts, bank_fields.slot);
+    let accounts_data_size_initial = bank_fields.accounts_data_len;
+    Bank::new_from_fields(
+        bank_rc,
+        genesis_config,
+        Arc::<RuntimeConfig>::default(),
+        bank_fields,
+        None,
+        None,
+        false,
+        accounts_data_size_initial,
+    )
+}
+
+impl TryFrom<ProtoBank> for BankFieldsToDeserialize {
+    type Error = SnapshotDecodeError;
+    fn try_from(bank: ProtoBank) -> Result<Self, Self::Error> {
+        let mut ancestors = HashMap::with_capacity(bank.ancestors.len());
+        for (i, ancestor) in bank.ancestors.iter().enumerate() {
+            let value =
+                decode_int(ancestor.value, "value").within(format_args!("ancestors[{i}]"))?;
+            if ancestors.insert(ancestor.slot, value).is_some() {
+                return Err(SnapshotDecodeError::duplicate_key("slot", ancestor.slot)
+                    .within(format_args!("ancestors[{i}]")));
+            }
+        }
+        let mut hard_forks = Vec::with_capacity(bank.hard_forks.len());
+        let mut hard_fork_slots = HashSet::with_capacity(bank.hard_forks.len());
+        for (i, hard_fork) in bank.hard_forks.iter().enumerate() {
+            let count: usize =
+                decode_int(hard_fork.count, "count").within(format_args!("hard_forks[{i}]"))?;
+            if count == 0 {
+                return Err(
+                    SnapshotDecodeError::invalid_value("count", "must not be zero")
+                        .within(format_args!("hard_forks[{i}]")),
+                );
+            }
+            if !hard_fork_slots.insert(hard_fork.slot) {
+                return Err(SnapshotDecodeError::duplicate_key("slot", hard_fork.slot)
+                    .within(format_args!("hard_forks[{i}]")));
+            }
+            hard_forks.push((hard_fork.slot, count));
+        }
+        // NOTE: `HardForks::register()` only adds one to a count, so a corrupt count would take
+        // forever to register.  Build it through serde instead, sorted by slot the same as
+        // `register()` keeps it.
+        hard_forks.sort_unstable_by_key(|(slot, _count)| *slot);
+        let hard_forks: HardForks = bincode::deserialize(
+            &bincode::serialize(&hard_forks).expect("a Vec always serializes"),
+        )
+        .expect("HardForks is serialized as its Vec of slots and counts");
+        let mut epoch_stakes = HashMap::with_capacity(bank.epoch_stakes.len());
+        for (i, epoch_stake) in bank.epoch_stakes.into_iter().enumerate() {
+            let (epoch, epoch_stake) = <(Epoch, EpochStakes)>::try_from(epoch_stake)
+                .within(format_args!("epoch_stakes[{i}]"))?;
+            if epoch_stakes.insert(epoch, epoch_stake).is_some() {
+                return Err(SnapshotDecodeError::duplicate_key("epoch", epoch)
+                    .within(format_args!("epoch_stakes[{i}]")));
+            }
         }
+        let epoch_reward_status = bank
+            .epoch_rewards
+            .map(EpochRewardStatus::try_from)
+            .transpose()
+            .within("epoch_rewards")?
+            .unwrap_or(EpochRewardStatus::Inactive);
+        Ok(Self {
+            blockhash_queue: decode_required(bank.blockhash_queue, "blockhash_queue")?,
+            ancestors,
+            hash: decode_bytes(&bank.hash, "hash")?,
+            parent_hash: decode_bytes(&bank.parent_hash, "parent_hash")?,
+            parent_slot: bank.parent_slot,
+            hard_forks,
+            transaction_count: bank.transaction_count,
+            tick_height: bank.tick_height,
+            signature_count: bank.signature_count,
+            capitalization: bank.capitalization,
+            max_tick_height: bank.max_tick_height,
+            hashes_per_tick: bank.hashes_per_tick,
+            ticks_per_slot: bank.ticks_per_slot,
+            ns_per_slot: required(bank.ns_per_slot, "ns_per_slot")?.into(),
+            genesis_creation_time: bank.genesis_creation_time,
+            slots_per_year: bank.slots_per_year,
+            slot: bank.slot,
+            epoch: bank.epoch,
+            block_height: bank.block_height,
+            collector_id: decode_bytes(&bank.collector_id, "collector_id")?,
+            collector_fees: bank.collector_fees,
+            fee_calculator: FeeCalculator::default(), // NOTE: deprecated, and not stored in the snapshot
+            fee_rate_governor: decode_required(bank.fee_rate_governor, "fee_rate_governor")?,
+            collected_rent: bank.collected_rent,
+            rent_collector: decode_required(bank.rent_collector, "rent_collector")?,
+            epoch_schedule: required(bank.epoch_schedule, "epoch_schedule")?.into(),
+            inflation: required(bank.inflation, "inflation")?.into(),
+            stakes: decode_required(bank.stakes, "stakes")?,
+            epoch_stakes,
+            is_delta: bank.is_delta,
+            accounts_data_len: bank.accounts_data_size,
+            incremental_snapshot_persistence: bank
+                .incremental_snapshot_persistence
+                .map(BankIncrementalSnapshotPersistence::try_from)
+                .transpose()
+                .within("incremental_snapshot_persistence")?,
+            epoch_accounts_hash: bank
+                .epoch_accounts_hash
+                .map(|epoch_accounts_hash| {
+                    decode_bytes(&epoch_accounts_hash, "epoch_accounts_hash")
+                })
+                .transpose()?,
+            epoch_reward_status,
+        })
+    }
+}
+
+/// Decodes the bank's fields, and checks that they agree with each other
+fn decode_bank(bank: ProtoBank) -> Result<BankFieldsToDeserialize, SnapshotDecodeError> {
+    let bank = BankFieldsToDeserialize::try_from(bank)?;
+    check_bank_consistency(&bank)?;
+    Ok(bank)
+}
+
+/// Checks the bank's fields that are derived from its other fields
+///
+/// `Bank::new_from_fields()` asserts all of these, so a snapshot that gets them wrong must be
+/// rejected here rather than panic when the bank is rebuilt.  See `check_bank_fields()` for the
+/// ones that depend on the genesis config.
+fn check_bank_consistency(bank: &BankFieldsToDeserialize) -> Result<(), SnapshotDecodeError> {
+    const NANOS_PER_SEC: u128 = 1_000_000_000;
+    let mismatch = |field, expected: &dyn Display| {
+        SnapshotDecodeError::invalid_value(field, format_args!("expected {expected}"))
+    };
+    let ticks_per_slot = u128::from(bank.ticks_per_slot);
+    if ticks_per_slot == 0 {
+        return Err(SnapshotDecodeError::invalid_value(
+            "ticks_per_slot",
+            "must be non-zero",
+        ));
+    }
+    if !bank.ns_per_slot.is_multiple_of(ticks_per_slot) {
+        return Err(SnapshotDecodeError::invalid_value(
+            "ns_per_slot",
+            format_args!("must be a multiple of ticks_per_slot {ticks_per_slot}"),
+        ));
+    }
+    let ns_per_tick = bank.ns_per_slot / ticks_per_slot;
+    let target_tick_duration = Duration::new(
+        decode_int(ns_per_tick / NANOS_PER_SEC, "ns_per_slot")?,
+        (ns_per_tick % NANOS_PER_SEC) as u32,
+    );
+    let max_tick_height = bank
+        .slot
+        .checked_add(1)
+        .and_then(|slots| slots.checked_mul(bank.ticks_per_slot))
+        .ok_or_else(|| SnapshotDecodeError::out_of_range("max_tick_height", "overflow", "u64"))?;
+    if bank.max_tick_height != max_tick_height {
+        return Err(mismatch("max_tick_height", &max_tick_height));
+    }
+    let slots_per_year = years_as_slots(1.0, &target_tick_duration, bank.ticks_per_slot);
+    if bank.slots_per_year != slots_per_year {
+        return Err(mismatch("slots_per_year", &slots_per_year));
+    }
+    let epoch = bank.epoch_schedule.get_epoch(bank.slot);
+    if bank.epoch != epoch {
+        return Err(mismatch("epoch", &epoch));
     }
+    Ok(())
 }
 
-impl From<ProtoBank> for Bank {
-    fn from(bank: ProtoBank) -> Self {
-        println!("proto bank: {bank:?}");
-
-        /*
-            let bank_fields = BankFieldsToDeserialize {
-                        blockhash_queue: todo!(),
-            ancestors: todo!(),
-            hash: *bytemuck::cast_ref(fb_bank.hash().unwrap()),
-            parent_hash: *bytemuck::cast_ref(fb_bank.parent_hash().unwrap()),
-            parent_slot: fb_bank.parent_slot(),
-            hard_forks: todo!(),
-            transaction_count: fb_bank.transaction_count(),
-            tick_height: fb_bank.tick_height(),
-            signature_count: fb_bank.signature_count(),
-            capitalization: fb_bank.capitalization(),
-            max_tick_height: fb_bank.max_tick_height(),
-            hashes_per_tick: fb_bank.hashes_per_tick(),
-            ticks_per_slot: fb_bank.ticks_per_slot(),
-            ns_per_slot: *bytemuck::cast_ref(fb_bank.ns_per_slot().unwrap()),
-            genesis_creation_time: todo!(),
-            slots_per_year: fb_bank.slots_per_year(),
-            slot: fb_bank.slot(),
-            epoch: fb_bank.epoch(),
-            block_height: fb_bank.block_height(),
-            collector_id: *bytemuck::cast_ref(fb_bank.collector_id().unwrap()),
-            collector_fees: fb_bank.collector_fees(),
-            fee_calculator: todo!(),
-            fee_rate_governor: todo!(),
-            collected_rent: fb_bank.collected_rent(),
-            rent_collector: todo!(), // TODO: I think this is unused?
-            epoch_schedule: todo!(),
-            inflation: todo!(),
-            stakes: todo!(),       //Stakes<Delegation>,
-            epoch_stakes: todo!(), //HashMap<Epoch, EpochStakes>,
-            is_delta: fb_bank.is_delta(),
-            accounts_data_len: fb_bank.accounts_data_size(),
-            incremental_snapshot_persistence: todo!(), //Option<BankIncrementalSnapshotPersistence>,
-            epoch_accounts_hash: todo!(),
-            epoch_reward_status: todo!(), //EpochRewardStatus,
-
-
-
-
-
-
-        /*
-         * blockhash_queue: BlockhashQueue,
-         * ancestors: AncestorsForSerialization,
-         * hash: Hash,
-         * parent_hash: Hash,
-         * parent_slot: Slot,
-         * hard_forks: HardForks,
-         * transaction_count: u64,
-         * tick_height: u64,
-         * signature_count: u64,
-         * capitalization: u64,
-         * max_tick_height: u64,
-         * hashes_per_tick: Option<u64>,
-         * ticks_per_slot: u64,
-         * ns_per_slot: u128,
-         * genesis_creation_time: UnixTimestamp,
-         * slots_per_year: f64,
-         * slot: Slot,
-         * epoch: Epoch,
-         * block_height: u64,
-         * collector_id: Pubkey,
-         * collector_fees: u64,
-         * fee_calculator: FeeCalculator,
-         * fee_rate_governor: FeeRateGovernor,
-         * collected_rent: u64,
-         * rent_collector: RentCollector,
-         * epoch_schedule: EpochSchedule,
-         * inflation: Inflation,
-         * stakes: Stakes<Delegation>,
-         * epoch_stakes: HashMap<Epoch, EpochStakes>,
-         * is_delta: bool,
-         * accounts_data_len: u64,
-         * incremental_snapshot_persistence: Option<BankIncrementalSnapshotPersistence>,
-         * epoch_accounts_hash: Option<Hash>,
-         * epoch_reward_status: EpochRewardStatus,
-         */
+/// Checks that the bank's fields agree with `genesis_config`, which `Bank::new_from_fields()`
+/// asserts
+fn check_bank_fields(
+    bank: &BankFieldsToDeserialize,
+    genesis_config: &GenesisConfig,
+) -> Result<(), SnapshotDecodeError> {
+    let mismatch = |field, expected: &dyn Display| {
+        SnapshotDecodeError::invalid_value(field, format_args!("expected {expected}"))
+    };
+    if bank.genesis_creation_time != genesis_config.creation_time {
+        return Err(mismatch(
+            "genesis_creation_time",
+            &genesis_config.creation_time,
+        ));
+    }
+    if bank.ticks_per_slot != genesis_config.ticks_per_slot {
+        return Err(mismatch("ticks_per_slot", &genesis_config.ticks_per_slot));
+    }
+    let ns_per_slot = genesis_config
+        .poh_config
+        .target_tick_duration
+        .as_nanos()
+        .checked_mul(bank.ticks_per_slot.into())
+        .ok_or_else(|| SnapshotDecodeError::out_of_range("ns_per_slot", "overflow", "u128"))?;
+    if bank.ns_per_slot != ns_per_slot {
+        return Err(mismatch("ns_per_slot", &ns_per_slot));
+    }
+    if bank.epoch_schedule != genesis_config.epoch_schedule {
+        return Err(SnapshotDecodeError::invalid_value(
+            "epoch_schedule",
+            "does not match the genesis config",
+        ));
+    }
+    Ok(())
+}
+
+impl TryFrom<ProtoEpochStake> for (Epoch, EpochStakes) {
+    type Error = SnapshotDecodeError;
+    fn try_from(epoch_stake: ProtoEpochStake) -> Result<Self, Self::Error> {
+        let stakes: StakesEnum = decode_required(epoch_stake.stakes, "stakes")?;
+        // The rest of the epoch stakes are derived from the stakes, so rebuild them the same way
+        // the bank does and check that the snapshot agrees
+        let epoch_stakes = EpochStakes::new(Arc::new(stakes), epoch_stake.epoch);
+
+        if epoch_stake.total_stake != epoch_stakes.total_stake() {
+            return Err(SnapshotDecodeError::invalid_value(
+                "total_stake",
+                format_args!(
+                    "{} does not match the sum of the vote account stakes, {}",
+                    epoch_stake.total_stake,
+                    epoch_stakes.total_stake(),
+                ),
+            ));
+        }
+
+        let expected_node_id_to_vote_accounts = epoch_stakes.node_id_to_vote_accounts();
+        let mut node_id_to_vote_accounts =
+            HashMap::with_capacity(epoch_stake.node_ids_to_vote_accounts.len());
+        for (i, node_id_to_vote_accounts_entry) in epoch_stake
+            .node_ids_to_vote_accounts
+            .into_iter()
+            .enumerate()
+        {
+            let (node_id, node_vote_accounts) =
+                <(Pubkey, NodeVoteAccounts)>::try_from(node_id_to_vote_accounts_entry)
+                    .within(format_args!("node_ids_to_vote_accounts[{i}]"))?;
+            let result = if node_id_to_vote_accounts.contains_key(&node_id) {
+                Err(SnapshotDecodeError::duplicate_key("node_id", node_id))
+            } else if let Some(expected) = expected_node_id_to_vote_accounts.get(&node_id) {
+                check_node_vote_accounts(&node_vote_accounts, expected)
+            } else {
+                Err(SnapshotDecodeError::invalid_value(
+                    "node_id",
+                    format_args!("{node_id} has no staked vote accounts"),
+                ))
             };
+            result.within(format_args!("node_ids_to_vote_accounts[{i}]"))?;
+            node_id_to_vote_accounts.insert(node_id, node_vote_accounts);
+        }
+        if let Some(node_id) = expected_node_id_to_vote_accounts
+            .keys()
+            .find(|node_id| !node_id_to_vote_accounts.contains_key(node_id))
+        {
+            return Err(SnapshotDecodeError::invalid_value(
+                "node_ids_to_vote_accounts",
+                format_args!("missing staked node {node_id}"),
+            ));
+        }
+
+        let epoch_authorized_voters = epoch_stake
+            .epoch_authorized_voters
+            .into_iter()
+            .enumerate()
+            .map(|(i, epoch_authorized_voter)| {
+                <(Pubkey, Pubkey)>::try_from(epoch_authorized_voter)
+                    .within(format_args!("epoch_authorized_voters[{i}]"))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        if &epoch_authorized_voters != epoch_stakes.epoch_authorized_voters().as_ref() {
+            return Err(SnapshotDecodeError::invalid_value(
+                "epoch_authorized_voters",
+                "does not match the authorized voters of the vote accounts",
+            ));
+        }
+
+        // NOTE: Only the order of each node's vote accounts can differ from the rebuilt ones by
+        // now.  It comes from hash map iteration, so it means nothing, and the rebuilt ones are
+        // kept.  See `diff::epoch_stakes_eq()`.
+        Ok((epoch_stake.epoch, epoch_stakes))
+    }
+}
+
+/// Checks a node's vote accounts from the snapshot against the ones derived from the stakes
+fn check_node_vote_accounts(
+    node_vote_accounts: &NodeVoteAccounts,
+    expected: &NodeVoteAccounts,
+) -> Result<(), SnapshotDecodeError> {
+    let vote_accounts: HashSet<_> = node_vote_accounts.vote_accounts.iter().collect();
+    let expected_vote_accounts: HashSet<_> = expected.vote_accounts.iter().collect();
+    if vote_accounts.len() != node_vote_accounts.vote_accounts.len()
+        || vote_accounts != expected_vote_accounts
+    {
+        return Err(SnapshotDecodeError::invalid_value(
+            "vote_accounts",
+            "does not match the staked vote accounts of this node",
+        ));
+    }
+    if node_vote_accounts.total_stake != expected.total_stake {
+        return Err(SnapshotDecodeError::invalid_value(
+            "total_stake",
+            format_args!(
+                "{} does not match the sum of the vote account stakes, {}",
+                node_vote_accounts.total_stake, expected.total_stake,
+            ),
+        ));
+    }
+    Ok(())
+}
 
-            */
+impl TryFrom<ProtoNodeIdToVoteAccounts> for (Pubkey, NodeVoteAccounts) {
+    type Error = SnapshotDecodeError;
+    fn try_from(node_id_to_vote_accounts: ProtoNodeIdToVoteAccounts) -> Result<Self, Self::Error> {
+        let node_id = decode_bytes(&node_id_to_vote_accounts.node_id, "node_id")?;
+        let vote_accounts = node_id_to_vote_accounts
+            .vote_accounts
+            .iter()
+            .enumerate()
+            .map(|(i, vote_account)| decode_bytes(vote_account, format_args!("vote_accounts[{i}]")))
+            .collect::<Result<_, _>>()?;
+        Ok((
+            node_id,
+            NodeVoteAccounts {
+                vote_accounts,
+                total_stake: node_id_to_vote_accounts.total_stake,
+            },
+        ))
+    }
+}
 
-        // TODO: impl
-        Bank::default_for_tests()
+impl TryFrom<ProtoEpochAuthorizedVoter> for (Pubkey, Pubkey) {
+    type Error = SnapshotDecodeError;
+    fn try_from(epoch_authorized_voter: ProtoEpochAuthorizedVoter) -> Result<Self, Self::Error> {
+        Ok((
+            decode_bytes(&epoch_authorized_voter.vote_account, "vote_account")?,
+            decode_bytes(&epoch_authorized_voter.authorized_voter, "authorized_voter")?,
+        ))
+    }
+}
+
+impl TryFrom<&StartBlockHeightAndRewards> for ProtoEpochRewards {
+    type Error = SnapshotEncodeError;
+    fn try_from(
+        start_block_height_and_rewards: &StartBlockHeightAndRewards,
+    ) -> Result<Self, Self::Error> {
+        let epoch_stake_rewards = start_block_height_and_rewards
+            .calculated_epoch_stake_rewards
+            .iter()
+            .enumerate()
+            .map(|(i, stake_reward)| {
+                ProtoEpochStakeReward::try_from(stake_reward)
+                    .within(format_args!("epoch_stake_rewards[{i}]"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            start_block_height: start_block_height_and_rewards.start_block_height,
+            epoch_stake_rewards,
+        })
+    }
+}
+
+impl TryFrom<ProtoEpochRewards> for EpochRewardStatus {
+    type Error = SnapshotDecodeError;
+    fn try_from(epoch_rewards: ProtoEpochRewards) -> Result<Self, Self::Error> {
+        let calculated_epoch_stake_rewards = epoch_rewards
+            .epoch_stake_rewards
+            .into_iter()
+            .enumerate()
+            .map(|(i, epoch_stake_reward)| {
+                StakeReward::try_from(epoch_stake_reward)
+                    .within(format_args!("epoch_stake_rewards[{i}]"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::Active(StartBlockHeightAndRewards {
+            start_block_height: epoch_rewards.start_block_height,
+            calculated_epoch_stake_rewards: Arc::new(calculated_epoch_stake_rewards),
+        }))
+    }
+}
+
+impl TryFrom<&StakeReward> for ProtoEpochStakeReward {
+    type Error = SnapshotEncodeError;
+    fn try_from(stake_reward: &StakeReward) -> Result<Self, Self::Error> {
+        Ok(Self {
+            stake_pubkey: stake_reward.stake_pubkey.to_bytes().into(),
+            stake_account: Some(stake_reward.stake_account.clone().into()), // TODO: avoid the clone
+            stake_reward_info: Some(
+                (&stake_reward.stake_reward_info)
+                    .try_into()
+                    .within("stake_reward_info")?,
+            ),
+        })
+    }
+}
+
+impl TryFrom<ProtoEpochStakeReward> for StakeReward {
+    type Error = SnapshotDecodeError;
+    fn try_from(epoch_stake_reward: ProtoEpochStakeReward) -> Result<Self, Self::Error> {
+        Ok(Self {
+            stake_pubkey: decode_bytes(&epoch_stake_reward.stake_pubkey, "stake_pubkey")?,
+            stake_reward_info: decode_required(
+                epoch_stake_reward.stake_reward_info,
+                "stake_reward_info",
+            )?,
+            stake_account: decode_required(epoch_stake_reward.stake_account, "stake_account")?,
+        })
+    }
+}
+
+impl TryFrom<&RewardInfo> for ProtoEpochStakeRewardInfo {
+    type Error = SnapshotEncodeError;
+    fn try_from(reward_info: &RewardInfo) -> Result<Self, Self::Error> {
+        Ok(Self {
+            reward_kind: ProtoEpochStakeRewardKind::from(reward_info.reward_type).into(),
+            lamports: encode_int(reward_info.lamports, "lamports")?,
+            post_balance: reward_info.post_balance,
+            commission: reward_info.commission.map(Into::into),
+        })
+    }
+}
+
+impl TryFrom<ProtoEpochStakeRewardInfo> for RewardInfo {
+    type Error = SnapshotDecodeError;
+    fn try_from(reward_info: ProtoEpochStakeRewardInfo) -> Result<Self, Self::Error> {
+        let reward_kind =
+            ProtoEpochStakeRewardKind::from_i32(reward_info.reward_kind).ok_or_else(|| {
+                SnapshotDecodeError::invalid_value(
+                    "reward_kind",
+                    format_args!("unknown enum value {}", reward_info.reward_kind),
+                )
+            })?;
+        Ok(Self {
+            reward_type: reward_kind.into(),
+            lamports: decode_int(reward_info.lamports, "lamports")?,
+            post_balance: reward_info.post_balance,
+            commission: reward_info
+                .commission
+                .map(|commission| decode_int(commission, "commission"))
+                .transpose()?,
+        })
+    }
+}
+
+impl From<RewardType> for ProtoEpochStakeRewardKind {
+    fn from(reward_type: RewardType) -> Self {
+        match reward_type {
+            RewardType::Fee => Self::Fee,
+            RewardType::Rent => Self::Rent,
+            RewardType::Staking => Self::Staking,
+            RewardType::Voting => Self::Voting,
+        }
+    }
+}
+
+impl From<ProtoEpochStakeRewardKind> for RewardType {
+    fn from(reward_kind: ProtoEpochStakeRewardKind) -> Self {
+        match reward_kind {
+            ProtoEpochStakeRewardKind::Fee => Self::Fee,
+            ProtoEpochStakeRewardKind::Rent => Self::Rent,
+            ProtoEpochStakeRewardKind::Staking => Self::Staking,
+            ProtoEpochStakeRewardKind::Voting => Self::Voting,
+        }
     }
 }
 
@@ -334,6 +996,19 @@ impl From<Inflation> for ProtoInflation {
     }
 }
 
+impl From<ProtoInflation> for Inflation {
+    fn from(inflation: ProtoInflation) -> Self {
+        // NOTE: Inflation has a private (and unused) field, so it cannot be built directly
+        let mut result = Inflation::default();
+        result.initial = inflation.initial;
+        result.terminal = inflation.terminal;
+        result.taper = inflation.taper;
+        result.foundation = inflation.foundation;
+        result.foundation_term = inflation.foundation_term;
+        result
+    }
+}
+
 impl From<FeeRateGovernor> for ProtoFeeRateGovernor {
     fn from(fee_rate_governor: FeeRateGovernor) -> Self {
         Self {
@@ -342,10 +1017,25 @@ impl From<FeeRateGovernor> for ProtoFeeRateGovernor {
             min_lamports_per_signature: fee_rate_governor.min_lamports_per_signature,
             max_lamports_per_signature: fee_rate_governor.max_lamports_per_signature,
             burn_percent: fee_rate_governor.burn_percent.into(),
+            target_lamports_per_signature: fee_rate_governor.target_lamports_per_signature,
         }
     }
 }
 
+impl TryFrom<ProtoFeeRateGovernor> for FeeRateGovernor {
+    type Error = SnapshotDecodeError;
+    fn try_from(fee_rate_governor: ProtoFeeRateGovernor) -> Result<Self, Self::Error> {
+        Ok(Self {
+            lamports_per_signature: fee_rate_governor.lamports_per_signature,
+            target_lamports_per_signature: fee_rate_governor.target_lamports_per_signature,
+            target_signatures_per_slot: fee_rate_governor.target_signatures_per_slot,
+            min_lamports_per_signature: fee_rate_governor.min_lamports_per_signature,
+            max_lamports_per_signature: fee_rate_governor.max_lamports_per_signature,
+            burn_percent: decode_int(fee_rate_governor.burn_percent, "burn_percent")?,
+        })
+    }
+}
+
 impl From<&BankIncrementalSnapshotPersistence> for ProtoIncrementalSnapshotPersistence {
     fn from(incremental_snapshot_persistence: &BankIncrementalSnapshotPersistence) -> Self {
         Self {
@@ -366,6 +1056,24 @@ impl From<&BankIncrementalSnapshotPersistence> for ProtoIncrementalSnapshotPersi
     }
 }
 
+impl TryFrom<ProtoIncrementalSnapshotPersistence> for BankIncrementalSnapshotPersistence {
+    type Error = SnapshotDecodeError;
+    fn try_from(
+        incremental_snapshot_persistence: ProtoIncrementalSnapshotPersistence,
+    ) -> Result<Self, Self::Error> {
+        Ok(Self {
+            full_slot: incremental_snapshot_persistence.full_slot,
+            full_hash: decode_bytes(&incremental_snapshot_persistence.full_hash, "full_hash")?,
+            full_capitalization: incremental_snapshot_persistence.full_capitalization,
+            incremental_hash: decode_bytes(
+                &incremental_snapshot_persistence.incremental_hash,
+                "incremental_hash",
+            )?,
+            incremental_capitalization: incremental_snapshot_persistence.incremental_capitalization,
+        })
+    }
+}
+
 impl From<RentCollector> for ProtoRentCollector {
     fn from(rent_collector: RentCollector) -> Self {
         Self {
@@ -377,6 +1085,18 @@ impl From<RentCollector> for ProtoRentCollector {
     }
 }
 
+impl TryFrom<ProtoRentCollector> for RentCollector {
+    type Error = SnapshotDecodeError;
+    fn try_from(rent_collector: ProtoRentCollector) -> Result<Self, Self::Error> {
+        Ok(Self {
+            epoch: rent_collector.epoch,
+            epoch_schedule: required(rent_collector.epoch_schedule, "epoch_schedule")?.into(),
+            slots_per_year: rent_collector.slots_per_year,
+            rent: decode_required(rent_collector.rent, "rent")?,
+        })
+    }
+}
+
 impl From<Rent> for ProtoRent {
     fn from(rent: Rent) -> Self {
         Self {
@@ -387,6 +1107,17 @@ impl From<Rent> for ProtoRent {
     }
 }
 
+impl TryFrom<ProtoRent> for Rent {
+    type Error = SnapshotDecodeError;
+    fn try_from(rent: ProtoRent) -> Result<Self, Self::Error> {
+        Ok(Self {
+            lamports_per_byte_year: rent.lamports_per_byte_year,
+            exemption_threshold: rent.exemption_threshold,
+            burn_percent: decode_int(rent.burn_percent, "burn_percent")?,
+        })
+    }
+}
+
 impl From<EpochSchedule> for ProtoEpochSchedule {
     fn from(epoch_schedule: EpochSchedule) -> Self {
         Self {
@@ -399,14 +1130,92 @@ impl From<EpochSchedule> for ProtoEpochSchedule {
     }
 }
 
-impl From<&BlockhashQueue> for ProtoBlockhashQueue {
-    fn from(blockhash_queue: &BlockhashQueue) -> Self {
+impl From<ProtoEpochSchedule> for EpochSchedule {
+    fn from(epoch_schedule: ProtoEpochSchedule) -> Self {
         Self {
+            slots_per_epoch: epoch_schedule.slots_per_epoch,
+            leader_schedule_slot_offset: epoch_schedule.leader_schedule_slot_offset,
+            warmup: epoch_schedule.warmup,
+            first_normal_epoch: epoch_schedule.first_normal_epoch,
+            first_normal_slot: epoch_schedule.first_normal_slot,
+        }
+    }
+}
+
+impl TryFrom<&BlockhashQueue> for ProtoBlockhashQueue {
+    type Error = SnapshotEncodeError;
+    fn try_from(blockhash_queue: &BlockhashQueue) -> Result<Self, Self::Error> {
+        Ok(Self {
             last_hash_index: blockhash_queue.last_hash_index,
             last_hash: blockhash_queue.last_hash.map(|hash| hash.to_bytes().into()),
-            max_age: blockhash_queue.max_age.try_into().unwrap(),
+            max_age: encode_int(blockhash_queue.max_age, "max_age")?,
             ages: blockhash_queue.ages.iter().map(Into::into).collect(),
+        })
+    }
+}
+
+impl TryFrom<ProtoBlockhashQueue> for BlockhashQueue {
+    type Error = SnapshotDecodeError;
+    fn try_from(blockhash_queue: ProtoBlockhashQueue) -> Result<Self, Self::Error> {
+        let last_hash_index = blockhash_queue.last_hash_index;
+        let mut ages = HashMap::with_capacity(blockhash_queue.ages.len());
+        for (i, age) in blockhash_queue.ages.into_iter().enumerate() {
+            let (hash, age) =
+                <(Hash, BlockhashAge)>::try_from(age).within(format_args!("ages[{i}]"))?;
+            if age.hash_index > last_hash_index {
+                return Err(SnapshotDecodeError::invalid_value(
+                    "hash_index",
+                    format_args!(
+                        "{} exceeds last_hash_index {last_hash_index}",
+                        age.hash_index
+                    ),
+                )
+                .within(format_args!("ages[{i}]")));
+            }
+            if ages.insert(hash, age).is_some() {
+                return Err(SnapshotDecodeError::duplicate_key("hash", hash)
+                    .within(format_args!("ages[{i}]")));
+            }
+        }
+
+        let last_hash = blockhash_queue
+            .last_hash
+            .map(|hash| decode_bytes(&hash, "last_hash"))
+            .transpose()?;
+        // The last hash must be the newest entry in the queue, since that is what new
+        // transactions are checked against
+        match last_hash {
+            Some(last_hash) => {
+                let last_hash_age = ages.get(&last_hash).ok_or_else(|| {
+                    SnapshotDecodeError::invalid_value(
+                        "last_hash",
+                        format_args!("{last_hash} is not in ages"),
+                    )
+                })?;
+                if let Some((hash, age)) = ages.iter().find(|(hash, age)| {
+                    **hash != last_hash && age.hash_index >= last_hash_age.hash_index
+                }) {
+                    return Err(SnapshotDecodeError::invalid_value(
+                        "last_hash",
+                        format_args!(
+                            "{last_hash} has hash index {}, but {hash} has hash index {}",
+                            last_hash_age.hash_index, age.hash_index,
+                        ),
+                    ));
+                }
+            }
+            None if !ages.is_empty() => {
+                return Err(SnapshotDecodeError::missing_field("last_hash"))
+            }
+            None => {}
         }
+
+        Ok(Self {
+            last_hash_index,
+            last_hash,
+            max_age: decode_int(blockhash_queue.max_age, "max_age")?,
+            ages,
+        })
     }
 }
 
@@ -421,6 +1230,20 @@ impl From<(&Hash, &BlockhashAge)> for ProtoBlockhashAge {
     }
 }
 
+impl TryFrom<ProtoBlockhashAge> for (Hash, BlockhashAge) {
+    type Error = SnapshotDecodeError;
+    fn try_from(blockhash_age: ProtoBlockhashAge) -> Result<Self, Self::Error> {
+        Ok((
+            decode_bytes(&blockhash_age.hash, "hash")?,
+            BlockhashAge {
+                fee_calculator: required(blockhash_age.fee_calculator, "fee_calculator")?.into(),
+                hash_index: blockhash_age.hash_index,
+                timestamp: blockhash_age.timestamp,
+            },
+        ))
+    }
+}
+
 impl From<FeeCalculator> for ProtoFeeCalculator {
     fn from(fee_calculator: FeeCalculator) -> Self {
         Self {
@@ -429,6 +1252,14 @@ impl From<FeeCalculator> for ProtoFeeCalculator {
     }
 }
 
+impl From<ProtoFeeCalculator> for FeeCalculator {
+    fn from(fee_calculator: ProtoFeeCalculator) -> Self {
+        Self {
+            lamports_per_signature: fee_calculator.lamports_per_signature,
+        }
+    }
+}
+
 impl From<&StakesCache> for ProtoStakes {
     fn from(stakes_cache: &StakesCache) -> Self {
         let stakes = Stakes::<Delegation>::from(stakes_cache.0.read().unwrap().clone());
@@ -497,6 +1328,129 @@ impl From<Stakes<Delegation>> for ProtoStakes {
     }
 }
 
+impl TryFrom<ProtoStakes> for Stakes<Delegation> {
+    type Error = SnapshotDecodeError;
+    fn try_from(stakes: ProtoStakes) -> Result<Self, Self::Error> {
+        let mut vote_account_pubkeys = HashSet::with_capacity(stakes.vote_accounts.len());
+        let vote_accounts = stakes
+            .vote_accounts
+            .into_iter()
+            .map(|vote_accounts_entry| {
+                let (pubkey, stake_and_vote_account) =
+                    <(Pubkey, (u64, VoteAccount))>::try_from(vote_accounts_entry)?;
+                if !vote_account_pubkeys.insert(pubkey) {
+                    return Err(SnapshotDecodeError::duplicate_key("pubkey", pubkey));
+                }
+                Ok((pubkey, stake_and_vote_account))
+            })
+            .enumerate()
+            .map(|(i, result)| result.within(format_args!("vote_accounts[{i}]")))
+            .collect::<Result<_, _>>()?;
+
+        let mut stake_delegation_pubkeys = HashSet::with_capacity(stakes.stake_delegations.len());
+        let stake_delegations = stakes
+            .stake_delegations
+            .into_iter()
+            .map(|stake_delegations_entry| {
+                let (pubkey, delegation) =
+                    <(Pubkey, Delegation)>::try_from(stake_delegations_entry)?;
+                if !stake_delegation_pubkeys.insert(pubkey) {
+                    return Err(SnapshotDecodeError::duplicate_key("pubkey", pubkey));
+                }
+                Ok((pubkey, delegation))
+            })
+            .enumerate()
+            .map(|(i, result)| result.within(format_args!("stake_delegations[{i}]")))
+            .collect::<Result<_, _>>()?;
+
+        let mut stake_history = StakeHistory::default();
+        for (i, proto_stake_history) in stakes.stake_history.into_iter().enumerate() {
+            let (epoch, stake_history_entry) = proto_stake_history.into();
+            if stake_history.get(epoch).is_some() {
+                return Err(SnapshotDecodeError::duplicate_key("epoch", epoch)
+                    .within(format_args!("stake_history[{i}]")));
+            }
+            stake_history.add(epoch, stake_history_entry);
+        }
+
+        Ok(Self {
+            vote_accounts,
+            stake_delegations,
+            unused: 0, // NOTE: unused, and not stored in the snapshot
+            epoch: stakes.epoch,
+            stake_history,
+        })
+    }
+}
+
+impl TryFrom<ProtoStakes> for StakesEnum {
+    type Error = SnapshotDecodeError;
+    fn try_from(stakes: ProtoStakes) -> Result<Self, Self::Error> {
+        Stakes::try_from(stakes).map(Self::Delegations)
+    }
+}
+
+impl TryFrom<ProtoVoteAccountsEntry> for (Pubkey, (u64, VoteAccount)) {
+    type Error = SnapshotDecodeError;
+    fn try_from(vote_accounts_entry: ProtoVoteAccountsEntry) -> Result<Self, Self::Error> {
+        let pubkey = decode_bytes(&vote_accounts_entry.pubkey, "pubkey")?;
+        let account: AccountSharedData =
+            decode_required(vote_accounts_entry.vote_account, "vote_account")?;
+        let vote_account = VoteAccount::try_from(account)
+            .map_err(|err| SnapshotDecodeError::invalid_vote_account("vote_account", err))?;
+        // The vote state is deserialized lazily, so check it here instead of at first use
+        vote_account
+            .vote_state()
+            .map_err(|err| SnapshotDecodeError::invalid_vote_account("vote_account", err))?;
+        Ok((pubkey, (vote_accounts_entry.stake, vote_account)))
+    }
+}
+
+impl TryFrom<ProtoStakeDelegationsEntry> for (Pubkey, Delegation) {
+    type Error = SnapshotDecodeError;
+    fn try_from(stake_delegations_entry: ProtoStakeDelegationsEntry) -> Result<Self, Self::Error> {
+        let pubkey = decode_bytes(&stake_delegations_entry.pubkey, "pubkey")?;
+        let delegation = required(stake_delegations_entry.delegation, "delegation")?;
+        let delegation = Delegation {
+            voter_pubkey: decode_bytes(&delegation.voter_pubkey, "voter_pubkey")
+                .within("delegation")?,
+            stake: delegation.stake,
+            activation_epoch: delegation.activation_epoch,
+            deactivation_epoch: delegation.deactivation_epoch,
+            warmup_cooldown_rate: delegation.warmup_cooldown_rate,
+        };
+        Ok((pubkey, delegation))
+    }
+}
+
+impl From<ProtoStakeHistory> for (Epoch, StakeHistoryEntry) {
+    fn from(stake_history: ProtoStakeHistory) -> Self {
+        (
+            stake_history.epoch,
+            StakeHistoryEntry {
+                effective: stake_history.effective,
+                activating: stake_history.activating,
+                deactivating: stake_history.deactivating,
+            },
+        )
+    }
+}
+
+impl From<u128> for ProtoUint128 {
+    fn from(value: u128) -> Self {
+        Self {
+            hi: (value >> 64) as u64,
+            lo: value as u64,
+        }
+    }
+}
+
+impl From<ProtoUint128> for u128 {
+    fn from(value: ProtoUint128) -> Self {
+        (u128::from(value.hi) << 64) | u128::from(value.lo)
+    }
+}
+
 impl<A: ReadableAccount> From<A> for ProtoAccount {
     fn from(account: A) -> Self {
         Self {
@@ -508,3 +1462,446 @@ impl<A: ReadableAccount> From<A> for ProtoAccount {
         }
     }
 }
+
+impl TryFrom<ProtoAccount> for AccountSharedData {
+    type Error = SnapshotDecodeError;
+    fn try_from(account: ProtoAccount) -> Result<Self, Self::Error> {
+        Ok(Account {
+            lamports: account.lamports,
+            data: account.data,
+            owner: decode_bytes(&account.owner, "owner")?,
+            executable: account.executable,
+            rent_epoch: account.rent_epoch,
+        }
+        .into())
+    }
+}
+
+impl AccountsDbFields {
+    /// Returns the accounts db fields for a snapshot of `bank`, with only the storages newer than
+    /// `base_slot` if given
+    fn new(bank: &Bank, base_slot: Option<Slot>) -> Result<Self, SnapshotEncodeError> {
+        let accounts_db = &bank.rc.accounts.accounts_db;
+        let slot = bank.slot();
+        // NOTE: Sample the write version before the storages, same as the bincode format
+        let write_version = accounts_db.write_version.load(Ordering::Acquire);
+        let storage_entries = account_records::snapshot_storages(bank, base_slot)
+            .iter()
+            .map(|storage| StorageEntry {
+                slot: storage.slot(),
+                id: storage.append_vec_id(),
+                accounts_current_len: storage.accounts.len(),
+            })
+            .collect();
+        let accounts_delta_hash = accounts_db.get_accounts_delta_hash(slot).ok_or_else(|| {
+            SnapshotEncodeError::missing_state(
+                "accounts_delta_hash",
+                format_args!("no accounts delta hash for slot {slot}"),
+            )
+        })?;
+        // NOTE: Validators calculate the accounts hash *after* a snapshot is taken, so it is
+        // usually not found.  Incremental snapshots are based on a full snapshot's accounts hash,
+        // so calculate it for a full snapshot.  An incremental snapshot does not need the full
+        // accounts hash, so use a placeholder instead, same as the bincode format.
+        let accounts_hash = match accounts_db.get_accounts_hash(slot) {
+            Some((accounts_hash, _capitalization)) => accounts_hash,
+            None if base_slot.is_none() => calculate_accounts_hash(bank)?,
+            None => AccountsHash(Hash::default()),
+        };
+        let bank_hash_stats = accounts_db.get_bank_hash_stats(slot).ok_or_else(|| {
+            SnapshotEncodeError::missing_state(
+                "bank_hash_stats",
+                format_args!("no bank hash stats for slot {slot}"),
+            )
+        })?;
+        Ok(Self {
+            slot,
+            write_version,
+            storage_entries,
+            accounts_delta_hash,
+            accounts_hash,
+            bank_hash_stats,
+            include_slot_in_hash: bank.include_slot_in_hash() == IncludeSlotInHash::IncludeSlot,
+        })
+    }
+}
+
+/// Calculates the accounts hash of `bank` from its storages, and stores it in the accounts db
+///
+/// NOTE: `Bank::update_accounts_hash()` panics if the accounts do not add up to the bank's
+/// capitalization, so go through the accounts db and fail instead.
+fn calculate_accounts_hash(bank: &Bank) -> Result<AccountsHash, SnapshotEncodeError> {
+    let (accounts_hash, total_lamports) = bank.rc.accounts.accounts_db.update_accounts_hash(
+        CalcAccountsHashDataSource::Storages,
+        false,
+        bank.slot(),
+        &bank.ancestors,
+        Some(bank.capitalization()),
+        bank.epoch_schedule(),
+        bank.rent_collector(),
+        false,
+        bank.include_slot_in_hash(),
+    );
+    if total_lamports != bank.capitalization() {
+        return Err(SnapshotEncodeError::invalid_value(
+            "accounts_hash",
+            format_args!(
+                "accounts add up to {total_lamports} lamports, not the capitalization {}",
+                bank.capitalization(),
+            ),
+        ));
+    }
+    Ok(accounts_hash)
+}
+
+/// Calculates the incremental accounts hash of `bank` from its storages newer than `base_slot`, and
+/// stores it in the accounts db
+///
+/// NOTE: `Bank::update_incremental_accounts_hash()` unwraps the result, so go through the accounts
+/// db and fail instead, same as `calculate_accounts_hash()`.
+fn calculate_incremental_accounts_hash(
+    bank: &Bank,
+    base_slot: Slot,
+) -> Result<(IncrementalAccountsHash, u64), SnapshotEncodeError> {
+    let storages = bank.get_snapshot_storages(Some(base_slot));
+    let config = CalcAccountsHashConfig {
+        use_bg_thread_pool: false,
+        check_hash: false,
+        ancestors: None,
+        epoch_schedule: bank.epoch_schedule(),
+        rent_collector: bank.rent_collector(),
+        store_detailed_debug_info_on_failure: false,
+        include_slot_in_hash: bank.include_slot_in_hash(),
+    };
+    bank.rc
+        .accounts
+        .accounts_db
+        .update_incremental_accounts_hash(
+            &config,
+            &SortedStorages::new(&storages),
+            bank.slot(),
+            HashStats::default(),
+        )
+        .map_err(|err| {
+            SnapshotEncodeError::invalid_value(
+                "incremental_hash",
+                format_args!("failed to calculate the incremental accounts hash: {err:?}"),
+            )
+        })
+}
+
+impl TryFrom<AccountsDbFields> for ProtoAccountsDb {
+    type Error = SnapshotEncodeError;
+    fn try_from(accounts_db_fields: AccountsDbFields) -> Result<Self, Self::Error> {
+        let storage_entries = accounts_db_fields
+            .storage_entries
+            .into_iter()
+            .map(|storage_entry| -> Result<_, SnapshotEncodeError> {
+                Ok(ProtoStorageEntry {
+                    slot: storage_entry.slot,
+                    id: storage_entry.id,
+                    accounts_current_len: encode_int(
+                        storage_entry.accounts_current_len,
+                        "accounts_current_len",
+                    )?,
+                })
+            })
+            .collect::<Result<_, _>>()
+            .within("storage_entries")?;
+        Ok(Self {
+            slot: accounts_db_fields.slot,
+            write_version: accounts_db_fields.write_version,
+            storage_entries,
+            bank_hash_info: Some(ProtoBankHashInfo {
+                accounts_delta_hash: accounts_db_fields.accounts_delta_hash.0.to_bytes().into(),
+                accounts_hash: accounts_db_fields.accounts_hash.0.to_bytes().into(),
+                stats: Some(accounts_db_fields.bank_hash_stats.into()),
+            }),
+            include_slot_in_hash: accounts_db_fields.include_slot_in_hash,
+        })
+    }
+}
+
+impl TryFrom<ProtoAccountsDb> for AccountsDbFields {
+    type Error = SnapshotDecodeError;
+    fn try_from(accounts_db: ProtoAccountsDb) -> Result<Self, Self::Error> {
+        let mut ids = HashSet::with_capacity(accounts_db.storage_entries.len());
+        let storage_entries = accounts_db
+            .storage_entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, storage_entry)| {
+                if !ids.insert(storage_entry.id) {
+                    return Err(SnapshotDecodeError::duplicate_key("id", storage_entry.id)
+                        .within(format_args!("storage_entries[{i}]")));
+                }
+                Ok(StorageEntry {
+                    slot: storage_entry.slot,
+                    id: storage_entry.id,
+                    accounts_current_len: decode_int(
+                        storage_entry.accounts_current_len,
+                        "accounts_current_len",
+                    )
+                    .within(format_args!("storage_entries[{i}]"))?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let bank_hash_info = required(accounts_db.bank_hash_info, "bank_hash_info")?;
+        Ok(Self {
+            slot: accounts_db.slot,
+            write_version: accounts_db.write_version,
+            storage_entries,
+            accounts_delta_hash: decode_bytes(
+                &bank_hash_info.accounts_delta_hash,
+                "accounts_delta_hash",
+            )
+            .within("bank_hash_info")?,
+            accounts_hash: decode_bytes(&bank_hash_info.accounts_hash, "accounts_hash")
+                .within("bank_hash_info")?,
+            bank_hash_stats: required(bank_hash_info.stats, "stats")
+                .within("bank_hash_info")?
+                .into(),
+            include_slot_in_hash: accounts_db.include_slot_in_hash,
+        })
+    }
+}
+
+impl From<BankHashStats> for ProtoBankHashStats {
+    fn from(bank_hash_stats: BankHashStats) -> Self {
+        Self {
+            num_updated_accounts: bank_hash_stats.num_updated_accounts,
+            num_removed_accounts: bank_hash_stats.num_removed_accounts,
+            num_lamports_stored: bank_hash_stats.num_lamports_stored,
+            total_data_len: bank_hash_stats.total_data_len,
+            num_executable_accounts: bank_hash_stats.num_executable_accounts,
+        }
+    }
+}
+
+impl From<ProtoBankHashStats> for BankHashStats {
+    fn from(bank_hash_stats: ProtoBankHashStats) -> Self {
+        Self {
+            num_updated_accounts: bank_hash_stats.num_updated_accounts,
+            num_removed_accounts: bank_hash_stats.num_removed_accounts,
+            num_lamports_stored: bank_hash_stats.num_lamports_stored,
+            total_data_len: bank_hash_stats.total_data_len,
+            num_executable_accounts: bank_hash_stats.num_executable_accounts,
+        }
+    }
+}
+
+/// The length of the transaction key slices in the status cache
+///
+/// NOTE: This is `status_cache::CACHED_KEY_SIZE`, which is private.
+const CACHED_KEY_SIZE: usize = 20;
+
+impl TryFrom<&BankStatusCache> for ProtoStatusCache {
+    type Error = SnapshotEncodeError;
+    fn try_from(status_cache: &BankStatusCache) -> Result<Self, Self::Error> {
+        let mut slot_deltas = status_cache
+            .root_slot_deltas()
+            .iter()
+            .map(ProtoSlotDelta::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .within("slot_deltas")?;
+        slot_deltas.sort_unstable_by_key(|slot_delta| slot_delta.slot);
+        Ok(Self { slot_deltas })
+    }
+}
+
+impl TryFrom<ProtoStatusCache> for Vec<BankSlotDelta> {
+    type Error = SnapshotDecodeError;
+    fn try_from(status_cache: ProtoStatusCache) -> Result<Self, Self::Error> {
+        let mut slots = HashSet::with_capacity(status_cache.slot_deltas.len());
+        status_cache
+            .slot_deltas
+            .into_iter()
+            .map(|slot_delta| {
+                let slot_delta = BankSlotDelta::try_from(slot_delta)?;
+                if !slots.insert(slot_delta.0) {
+                    return Err(SnapshotDecodeError::duplicate_key("slot", slot_delta.0));
+                }
+                Ok(slot_delta)
+            })
+            .enumerate()
+            .map(|(i, result)| result.within(format_args!("slot_deltas[{i}]")))
+            .collect()
+    }
+}
+
+impl TryFrom<&BankSlotDelta> for ProtoSlotDelta {
+    type Error = SnapshotEncodeError;
+    fn try_from((slot, is_root, status): &BankSlotDelta) -> Result<Self, Self::Error> {
+        let mut statuses = status
+            .lock()
+            .unwrap()
+            .iter()
+            .map(
+                |(blockhash, (key_index, key_statuses))| -> Result<_, SnapshotEncodeError> {
+                    Ok(ProtoStatus {
+                        blockhash: blockhash.to_bytes().into(),
+                        key_index: encode_int(*key_index, "key_index")?,
+                        key_statuses: key_statuses
+                            .iter()
+                            .map(ProtoKeyStatus::try_from)
+                            .collect::<Result<_, _>>()
+                            .within("key_statuses")?,
+                    })
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()
+            .within("statuses")?;
+        statuses.sort_unstable_by(|a, b| a.blockhash.cmp(&b.blockhash));
+        Ok(Self {
+            slot: *slot,
+            is_root: *is_root,
+            statuses,
+        })
+    }
+}
+
+impl TryFrom<ProtoSlotDelta> for BankSlotDelta {
+    type Error = SnapshotDecodeError;
+    fn try_from(slot_delta: ProtoSlotDelta) -> Result<Self, Self::Error> {
+        let mut statuses = HashMap::with_capacity(slot_delta.statuses.len());
+        for (i, status) in slot_delta.statuses.into_iter().enumerate() {
+            let blockhash = decode_bytes(&status.blockhash, "blockhash")
+                .within(format_args!("statuses[{i}]"))?;
+            let key_index =
+                decode_int(status.key_index, "key_index").within(format_args!("statuses[{i}]"))?;
+            let key_statuses = status
+                .key_statuses
+                .into_iter()
+                .enumerate()
+                .map(|(j, key_status)| {
+                    <([u8; CACHED_KEY_SIZE], TransactionResult<()>)>::try_from(key_status)
+                        .within(format_args!("key_statuses[{j}]"))
+                })
+                .collect::<Result<_, _>>()
+                .within(format_args!("statuses[{i}]"))?;
+            if statuses
+                .insert(blockhash, (key_index, key_statuses))
+                .is_some()
+            {
+                return Err(SnapshotDecodeError::duplicate_key("blockhash", blockhash)
+                    .within(format_args!("statuses[{i}]")));
+            }
+        }
+        Ok((
+            slot_delta.slot,
+            slot_delta.is_root,
+            Arc::new(Mutex::new(statuses)),
+        ))
+    }
+}
+
+impl TryFrom<&([u8; CACHED_KEY_SIZE], TransactionResult<()>)> for ProtoKeyStatus {
+    type Error = SnapshotEncodeError;
+    fn try_from(
+        (key_slice, result): &([u8; CACHED_KEY_SIZE], TransactionResult<()>),
+    ) -> Result<Self, Self::Error> {
+        let error = result
+            .as_ref()
+            .err()
+            .map(|err| {
+                bincode::serialize(err)
+                    .map_err(|err| SnapshotEncodeError::invalid_value("error", err))
+            })
+            .transpose()?;
+        Ok(Self {
+            key_slice: key_slice.to_vec(),
+            error,
+        })
+    }
+}
+
+impl TryFrom<ProtoKeyStatus> for ([u8; CACHED_KEY_SIZE], TransactionResult<()>) {
+    type Error = SnapshotDecodeError;
+    fn try_from(key_status: ProtoKeyStatus) -> Result<Self, Self::Error> {
+        let key_slice = decode_bytes(&key_status.key_slice, "key_slice")?;
+        let result = match key_status.error {
+            None => Ok(()),
+            Some(error) => Err(bincode::deserialize(&error)
+                .map_err(|err| SnapshotDecodeError::invalid_value("error", err))?),
+        };
+        Ok((key_slice, result))
+    }
+}
+
+/// Reads the length-delimited message `field` from `reader`, using `buf` for its bytes
+///
+/// The message is required, so it is an error if `reader` was already exhausted.
+fn read_required_message<M: Message + Default>(
+    reader: &mut impl Read,
+    buf: &mut Vec<u8>,
+    field: &str,
+) -> Result<M, SnapshotDecodeError> {
+    if !read_length_delimited(reader, buf)? {
+        return Err(SnapshotDecodeError::missing_field(field));
+    }
+    Ok(M::decode(buf.as_slice())?)
+}
+
+/// Reads one length-delimited message from `reader` into `buf`
+///
+/// Returns false if `reader` was already exhausted.
+fn read_length_delimited(reader: &mut impl Read, buf: &mut Vec<u8>) -> io::Result<bool> {
+    // The length prefix is a varint of at most 10 bytes, 7 bits at a time
+    let mut len = 0u64;
+    for i in 0..10 {
+        let mut byte = [0u8];
+        if reader.read(&mut byte)? == 0 {
+            if i == 0 {
+                return Ok(false);
+            }
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        len |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            buf.clear();
+            // NOTE: Read through `take()` so a corrupt length cannot allocate more than is there
+            reader.by_ref().take(len).read_to_end(buf)?;
+            if buf.len() as u64 != len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            return Ok(true);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "length prefix is longer than 10 bytes",
+    ))
+}
+
+/// Returns the value of a required message field, or an error naming the missing field
+fn required<T>(value: Option<T>, field: &'static str) -> Result<T, SnapshotDecodeError> {
+    value.ok_or_else(|| SnapshotDecodeError::missing_field(field))
+}
+
+/// Decodes a required message field, prefixing any decode error with the field's name
+fn decode_required<T, U>(value: Option<U>, field: &'static str) -> Result<T, SnapshotDecodeError>
+where
+    T: TryFrom<U, Error = SnapshotDecodeError>,
+{
+    T::try_from(required(value, field)?).within(field)
+}
+
+/// Converts an integer from its runtime type into its (narrower) schema type
+fn encode_int<T, U>(value: U, field: &'static str) -> Result<T, SnapshotEncodeError>
+where
+    T: TryFrom<U>,
+    U: Copy + Display,
+{
+    T::try_from(value)
+        .map_err(|_| SnapshotEncodeError::out_of_range(field, value, std::any::type_name::<T>()))
+}
+
+/// Converts an integer from its schema type into its (narrower) runtime type
+fn decode_int<T, U>(value: U, field: &'static str) -> Result<T, SnapshotDecodeError>
+where
+    T: TryFrom<U>,
+    U: Copy + Display,
+{
+    T::try_from(value)
+        .map_err(|_| SnapshotDecodeError::out_of_range(field, value, std::any::type_name::<T>()))
+}
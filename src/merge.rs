@@ -0,0 +1,98 @@
+use {
+    crate::{error::SnapshotMergeError, SnapshotFields, SnapshotKind},
+    std::collections::HashSet,
+};
+
+/// Applies an incremental snapshot onto the full snapshot it is based on, the way a validator
+/// boots from a full and an incremental snapshot archive
+///
+/// The bank and status cache come from the incremental snapshot, and the storages are the full
+/// snapshot's, up to its slot, plus the incremental snapshot's.  The result is a full snapshot at
+/// the incremental snapshot's slot.
+///
+/// NOTE: The accounts hash is the incremental snapshot's, which is a placeholder unless the full
+/// accounts hash at its slot had already been calculated when it was taken.
+///
+/// NOTE: Only the snapshots' fields are merged.  Their accounts sections can be read one after the
+/// other, full first, since each storage's accounts come from exactly one of them.
+pub fn merge_incremental_snapshot(
+    full: SnapshotFields,
+    incremental: SnapshotFields,
+) -> Result<SnapshotFields, SnapshotMergeError> {
+    if full.kind != SnapshotKind::Full {
+        return Err(SnapshotMergeError::NotFull);
+    }
+    let SnapshotKind::Incremental {
+        base_slot,
+        base_hash,
+    } = incremental.kind
+    else {
+        return Err(SnapshotMergeError::NotIncremental);
+    };
+
+    if base_slot != full.bank.slot {
+        return Err(SnapshotMergeError::BaseSlotMismatch {
+            base_slot,
+            full_slot: full.bank.slot,
+        });
+    }
+    if base_hash != full.accounts_db.accounts_hash {
+        return Err(SnapshotMergeError::BaseHashMismatch);
+    }
+    let persistence = incremental
+        .bank
+        .incremental_snapshot_persistence
+        .as_ref()
+        .ok_or(SnapshotMergeError::MissingPersistence)?;
+    if persistence.full_slot != base_slot {
+        return Err(SnapshotMergeError::PersistenceMismatch { field: "full_slot" });
+    }
+    if persistence.full_hash.0 != base_hash.0 {
+        return Err(SnapshotMergeError::PersistenceMismatch { field: "full_hash" });
+    }
+    if persistence.full_capitalization != full.bank.capitalization {
+        return Err(SnapshotMergeError::PersistenceMismatch {
+            field: "full_capitalization",
+        });
+    }
+    if let Some(storage_entry) = incremental
+        .accounts_db
+        .storage_entries
+        .iter()
+        .find(|storage_entry| storage_entry.slot <= base_slot)
+    {
+        return Err(SnapshotMergeError::StaleStorage {
+            slot: storage_entry.slot,
+            base_slot,
+        });
+    }
+
+    let mut accounts_db = incremental.accounts_db;
+    let full_storage_entries = full
+        .accounts_db
+        .storage_entries
+        .into_iter()
+        .filter(|storage_entry| storage_entry.slot <= base_slot);
+    accounts_db.storage_entries = full_storage_entries
+        .chain(accounts_db.storage_entries)
+        .collect();
+    // NOTE: Storage ids are not remapped, so they must be unique across both snapshots
+    let mut ids = HashSet::with_capacity(accounts_db.storage_entries.len());
+    if let Some(storage_entry) = accounts_db
+        .storage_entries
+        .iter()
+        .find(|storage_entry| !ids.insert(storage_entry.id))
+    {
+        return Err(SnapshotMergeError::DuplicateStorageId {
+            id: storage_entry.id,
+        });
+    }
+
+    Ok(SnapshotFields {
+        header: incremental.header,
+        kind: SnapshotKind::Full,
+        bank: incremental.bank,
+        accounts_db,
+        status_cache: incremental.status_cache,
+    })
+}
@@ -0,0 +1,45 @@
+use {
+    crate::{
+        checksum::verify_split_checksums,
+        error::{ResultExt, SnapshotDecodeError},
+        fixed_bytes::decode_bytes,
+        schema::solana::snapshot::{
+            checksums::Signature as ProtoSignature, Checksums as ProtoChecksums,
+        },
+    },
+    solana_sdk::{
+        pubkey::Pubkey,
+        signature::{Keypair, Signature, Signer},
+    },
+    std::io::{Read, Seek, Take},
+};
+
+/// Signs the checksums' file hash, which covers everything before the checksums, with `keypair`
+pub fn sign_checksums(checksums: &mut ProtoChecksums, keypair: &Keypair) {
+    checksums.signature = Some(ProtoSignature {
+        signer: keypair.pubkey().to_bytes().into(),
+        signature: keypair.sign_message(&checksums.file_hash).as_ref().into(),
+    });
+}
+
+/// Checks a snapshot's checksums, then that it was signed by `trusted_signer`, and returns
+/// a reader of everything before the checksums, to be decoded
+pub fn verify_signature<R: Read + Seek>(
+    snapshot: R,
+    trusted_signer: &Pubkey,
+) -> Result<Take<R>, SnapshotDecodeError> {
+    let (body, checksums) = verify_split_checksums(snapshot)?;
+    let signature = checksums
+        .signature
+        .ok_or(SnapshotDecodeError::MissingSignature)?;
+    let signer: Pubkey = decode_bytes(&signature.signer, "signer").within("signature")?;
+    if signer != *trusted_signer {
+        return Err(SnapshotDecodeError::UntrustedSigner { signer });
+    }
+    let signature: Signature =
+        decode_bytes(&signature.signature, "signature").within("signature")?;
+    if !signature.verify(signer.as_ref(), &checksums.file_hash) {
+        return Err(SnapshotDecodeError::InvalidSignature);
+    }
+    Ok(body)
+}
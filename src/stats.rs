@@ -0,0 +1,219 @@
+use {
+    crate::{
+        checksum::verify_split_checksums,
+        decode_int,
+        error::{ResultExt, SnapshotDecodeError},
+        read_length_delimited, read_required_message,
+        schema::solana::snapshot::{
+            AccountRecord as ProtoAccountRecord, Bank as ProtoBank, Snapshot as ProtoSnapshot,
+            Stakes as ProtoStakes,
+        },
+    },
+    prost::Message,
+    std::io::{self, Read, Seek, SeekFrom},
+};
+
+/// How many bytes a field, or a section of the file, takes when encoded
+///
+/// Paths with `[*]` add up the field across every element of a repeated field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSize {
+    pub path: String,
+    pub len: usize,
+}
+
+/// Attributes the size of a snapshot to its sections, and to the fields within them that are
+/// likely to be big
+///
+/// Sections are sized exactly, from their checksums.  Fields are sized with `encoded_len()`, which
+/// leaves out each field's own tag and length prefix, so a message's fields add up to a little
+/// less than it.  Rows are in file order, each message followed by its fields.
+pub fn field_sizes(snapshot: impl Read + Seek) -> Result<Vec<FieldSize>, SnapshotDecodeError> {
+    let (mut body, checksums) = verify_split_checksums(snapshot)?;
+    let body_len = body.limit();
+    let mut sizes = Vec::new();
+    let mut buf = Vec::new();
+    for (i, section) in checksums.sections.iter().enumerate() {
+        let len = decode_int(section.len, "len").within(format_args!("sections[{i}]"))?;
+        let mut section_reader = body.by_ref().take(section.len);
+        sizes.push(FieldSize::new(&section.name, len));
+        match section.name.as_str() {
+            "snapshot" => {
+                let snapshot: ProtoSnapshot =
+                    read_required_message(&mut section_reader, &mut buf, "snapshot")?;
+                snapshot_sizes(&snapshot, &mut sizes);
+            }
+            "accounts" => account_record_sizes(&mut section_reader, &mut sizes)?,
+            // NOTE: The header is only a handful of small fields, so it is not broken down
+            _ => {}
+        }
+        // NOTE: Skip whatever of the section was not read, so the next one starts in the right place
+        io::copy(&mut section_reader, &mut io::sink())?;
+    }
+    let file_len = body.into_inner().seek(SeekFrom::End(0))?;
+    sizes.push(FieldSize::new(
+        "checksums",
+        decode_int(file_len - body_len, "checksums")?,
+    ));
+    Ok(sizes)
+}
+
+impl FieldSize {
+    fn new(path: impl Into<String>, len: usize) -> Self {
+        Self {
+            path: path.into(),
+            len,
+        }
+    }
+}
+
+fn snapshot_sizes(snapshot: &ProtoSnapshot, sizes: &mut Vec<FieldSize>) {
+    if let Some(bank) = &snapshot.bank {
+        sizes.push(FieldSize::new("bank", bank.encoded_len()));
+        bank_sizes(bank, sizes);
+    }
+    if let Some(accounts_db) = &snapshot.accounts_db {
+        sizes.push(FieldSize::new("accounts_db", accounts_db.encoded_len()));
+        sizes.push(FieldSize::new(
+            "accounts_db.storage_entries",
+            encoded_len_repeated(&accounts_db.storage_entries),
+        ));
+    }
+    if let Some(status_cache) = &snapshot.status_cache {
+        sizes.push(FieldSize::new("status_cache", status_cache.encoded_len()));
+        sizes.push(FieldSize::new(
+            "status_cache.slot_deltas[*].statuses",
+            status_cache
+                .slot_deltas
+                .iter()
+                .map(|slot_delta| encoded_len_repeated(&slot_delta.statuses))
+                .sum(),
+        ));
+    }
+}
+
+fn bank_sizes(bank: &ProtoBank, sizes: &mut Vec<FieldSize>) {
+    sizes.push(FieldSize::new(
+        "bank.ancestors",
+        encoded_len_repeated(&bank.ancestors),
+    ));
+    sizes.push(FieldSize::new(
+        "bank.hard_forks",
+        encoded_len_repeated(&bank.hard_forks),
+    ));
+    if let Some(blockhash_queue) = &bank.blockhash_queue {
+        sizes.push(FieldSize::new(
+            "bank.blockhash_queue",
+            blockhash_queue.encoded_len(),
+        ));
+    }
+    if let Some(stakes) = &bank.stakes {
+        sizes.push(FieldSize::new("bank.stakes", stakes.encoded_len()));
+        stakes_sizes("bank.stakes", [stakes], sizes);
+    }
+    sizes.push(FieldSize::new(
+        "bank.epoch_stakes",
+        encoded_len_repeated(&bank.epoch_stakes),
+    ));
+    let epoch_stakes = || bank.epoch_stakes.iter();
+    sizes.push(FieldSize::new(
+        "bank.epoch_stakes[*].stakes",
+        epoch_stakes()
+            .filter_map(|epoch_stake| epoch_stake.stakes.as_ref())
+            .map(Message::encoded_len)
+            .sum(),
+    ));
+    stakes_sizes(
+        "bank.epoch_stakes[*].stakes",
+        epoch_stakes().filter_map(|epoch_stake| epoch_stake.stakes.as_ref()),
+        sizes,
+    );
+    sizes.push(FieldSize::new(
+        "bank.epoch_stakes[*].node_ids_to_vote_accounts",
+        epoch_stakes()
+            .map(|epoch_stake| encoded_len_repeated(&epoch_stake.node_ids_to_vote_accounts))
+            .sum(),
+    ));
+    sizes.push(FieldSize::new(
+        "bank.epoch_stakes[*].epoch_authorized_voters",
+        epoch_stakes()
+            .map(|epoch_stake| encoded_len_repeated(&epoch_stake.epoch_authorized_voters))
+            .sum(),
+    ));
+    if let Some(epoch_rewards) = &bank.epoch_rewards {
+        sizes.push(FieldSize::new(
+            "bank.epoch_rewards",
+            epoch_rewards.encoded_len(),
+        ));
+    }
+}
+
+/// Adds up the fields of every `Stakes` message in `all_stakes`, which are all at `path`
+fn stakes_sizes<'a>(
+    path: &str,
+    all_stakes: impl IntoIterator<Item = &'a ProtoStakes> + Clone,
+    sizes: &mut Vec<FieldSize>,
+) {
+    let sum = |len: fn(&ProtoStakes) -> usize| all_stakes.clone().into_iter().map(len).sum();
+    sizes.push(FieldSize::new(
+        format!("{path}.vote_accounts"),
+        sum(|stakes| encoded_len_repeated(&stakes.vote_accounts)),
+    ));
+    sizes.push(FieldSize::new(
+        format!("{path}.vote_accounts[*].vote_account.data"),
+        sum(|stakes| {
+            stakes
+                .vote_accounts
+                .iter()
+                .filter_map(|entry| entry.vote_account.as_ref())
+                .map(|vote_account| vote_account.data.len())
+                .sum()
+        }),
+    ));
+    sizes.push(FieldSize::new(
+        format!("{path}.stake_delegations"),
+        sum(|stakes| encoded_len_repeated(&stakes.stake_delegations)),
+    ));
+    sizes.push(FieldSize::new(
+        format!("{path}.stake_history"),
+        sum(|stakes| encoded_len_repeated(&stakes.stake_history)),
+    ));
+}
+
+fn account_record_sizes(
+    accounts_section: &mut impl Read,
+    sizes: &mut Vec<FieldSize>,
+) -> Result<(), SnapshotDecodeError> {
+    let (mut account_len, mut data_len) = (0, 0);
+    let mut buf = Vec::new();
+    for index in 0.. {
+        let Some(record) = read_account_record(accounts_section, &mut buf)
+            .within(format_args!("accounts[{index}]"))?
+        else {
+            break;
+        };
+        if let Some(account) = &record.account {
+            account_len += account.encoded_len();
+            data_len += account.data.len();
+        }
+    }
+    sizes.push(FieldSize::new("accounts[*].account", account_len));
+    sizes.push(FieldSize::new("accounts[*].account.data", data_len));
+    Ok(())
+}
+
+/// Reads one account record as it is encoded, without converting it, or returns `None` if `reader`
+/// is exhausted
+fn read_account_record(
+    reader: &mut impl Read,
+    buf: &mut Vec<u8>,
+) -> Result<Option<ProtoAccountRecord>, SnapshotDecodeError> {
+    if !read_length_delimited(reader, buf)? {
+        return Ok(None);
+    }
+    Ok(Some(ProtoAccountRecord::decode(buf.as_slice())?))
+}
+
+fn encoded_len_repeated<M: Message>(messages: &[M]) -> usize {
+    messages.iter().map(Message::encoded_len).sum()
+}
@@ -0,0 +1,112 @@
+use {
+    solana_runtime::{
+        bank::Bank,
+        genesis_utils::{self, ValidatorVoteKeypairs},
+    },
+    solana_sdk::{
+        account::AccountSharedData,
+        clock::Slot,
+        genesis_config::GenesisConfig,
+        native_token::LAMPORTS_PER_SOL,
+        pubkey::Pubkey,
+        signature::{keypair_from_seed, Keypair, Signer},
+        system_program, system_transaction,
+    },
+    std::sync::Arc,
+};
+
+/// How many lamports the test genesis config gives the payer of test banks' transactions
+const PAYER_LAMPORTS: u64 = 1_000 * LAMPORTS_PER_SOL;
+
+/// Returns the keypair that pays for test banks' transactions
+///
+/// It is derived from a fixed seed, so that any bank from the test genesis config can use it.
+fn test_payer() -> Keypair {
+    keypair_from_seed(&[1; 32]).expect("the seed is long enough")
+}
+
+/// Returns the genesis config for test banks
+///
+/// It has three staked vote accounts, two of which are for the same node, so that the epoch
+/// stakes have a node with more than one vote account.  It also funds the test payer.
+pub fn test_genesis_config() -> GenesisConfig {
+    let node_keypair = Keypair::new();
+    let voting_keypairs = [
+        ValidatorVoteKeypairs::new(
+            node_keypair.insecure_clone(),
+            Keypair::new(),
+            Keypair::new(),
+        ),
+        ValidatorVoteKeypairs::new(node_keypair, Keypair::new(), Keypair::new()),
+        ValidatorVoteKeypairs::new_rand(),
+    ];
+    let stakes = vec![
+        300 * LAMPORTS_PER_SOL,
+        200 * LAMPORTS_PER_SOL,
+        100 * LAMPORTS_PER_SOL,
+    ];
+    let mut genesis_config = genesis_utils::create_genesis_config_with_vote_accounts(
+        1_000_000 * LAMPORTS_PER_SOL,
+        &voting_keypairs,
+        stakes,
+    )
+    .genesis_config;
+    genesis_config.add_account(
+        test_payer().pubkey(),
+        AccountSharedData::new(PAYER_LAMPORTS, 0, &system_program::id()),
+    );
+    genesis_config
+}
+
+/// Returns the genesis bank for tests
+///
+/// The bank has a hard fork at slot 2, registered twice, so that snapshots of its descendants
+/// have a hard fork with a count.
+pub fn genesis_test_bank(genesis_config: &GenesisConfig) -> Arc<Bank> {
+    let bank = Bank::new_for_tests(genesis_config);
+    let hard_forks = bank.hard_forks();
+    hard_forks.write().unwrap().register(2);
+    hard_forks.write().unwrap().register(2);
+    // NOTE: Fill the genesis slot too, so that every descendant is complete, as snapshot archives
+    // require
+    bank.fill_bank_with_ticks_for_tests();
+    Arc::new(bank)
+}
+
+/// Returns a bank `num_slots` slots past `parent`, with every slot filled with ticks
+///
+/// The first new slot also processes a transfer that succeeds and one that fails, so that the
+/// status cache has both kinds of statuses.  The bank is rooted and flushed, since snapshots are
+/// only taken of rooted banks.  This also keeps the bank's slot alive in the accounts db when
+/// rebuilt banks that share it are dropped.
+pub fn new_test_bank(parent: Arc<Bank>, num_slots: Slot) -> Arc<Bank> {
+    let mut bank = parent;
+    for i in 0..num_slots {
+        bank = Arc::new(Bank::new_from_parent(
+            &bank,
+            &Pubkey::new_unique(),
+            bank.slot() + 1,
+        ));
+        if i == 0 {
+            process_test_transfers(&bank);
+        }
+        bank.fill_bank_with_ticks_for_tests();
+    }
+    bank.freeze();
+    bank.squash();
+    bank.force_flush_accounts_cache();
+    bank
+}
+
+/// Processes a transfer from the test payer that succeeds, and one for more than it has that fails
+fn process_test_transfers(bank: &Bank) {
+    let payer = test_payer();
+    let recent_blockhash = bank.last_blockhash();
+    let transfer = |lamports| {
+        system_transaction::transfer(&payer, &Pubkey::new_unique(), lamports, recent_blockhash)
+    };
+    bank.process_transaction(&transfer(LAMPORTS_PER_SOL))
+        .expect("the payer can afford the transfer");
+    bank.process_transaction(&transfer(PAYER_LAMPORTS))
+        .expect_err("the payer cannot afford the transfer");
+}
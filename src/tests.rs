@@ -0,0 +1,1198 @@
+use {
+    crate::{
+        account_records::{
+            rebuild_accounts_db, rebuild_merged_accounts_db, snapshot_storages, AccountRecord,
+            AccountRecords,
+        },
+        calculate_accounts_hash,
+        canonical::Encoding,
+        checksum::{read_checksums, verify_checksums, ChecksumWriter},
+        convert::{
+            archive_to_snapshot, reencode_snapshot, snapshot_to_archive, verify_accounts_hash,
+        },
+        decode_bank, decode_snapshot,
+        diff::{diff_bank_fields, slot_deltas_eq},
+        error::{
+            ArchiveError, FieldError, SnapshotDecodeError, SnapshotEncodeError, SnapshotMergeError,
+        },
+        header::{SnapshotHeader, ALL_SECTIONS, SCHEMA_VERSION},
+        incremental_snapshot_persistence,
+        merge::merge_incremental_snapshot,
+        rebuild_bank,
+        schema::solana::snapshot::{
+            epoch_rewards::{
+                epoch_stake_reward::RewardInfo as ProtoEpochStakeRewardInfo,
+                EpochStakeReward as ProtoEpochStakeReward,
+            },
+            header::Section,
+            Bank as ProtoBank, EpochRewards as ProtoEpochRewards, HardFork as ProtoHardFork,
+            Header as ProtoHeader, Uint128 as ProtoUint128,
+        },
+        signing::verify_signature,
+        snapshot_bank, stats,
+        test_bank::{genesis_test_bank, new_test_bank, test_genesis_config},
+        AccountsDbFields, SnapshotFields, SnapshotKind, StorageEntry,
+    },
+    prost::Message,
+    solana_runtime::{
+        accounts_db::{AccountStorageEntry, AccountsHashVerificationError},
+        accounts_hash::AccountsHash,
+        bank::{
+            Bank, BankFieldsToDeserialize, BankSlotDelta, EpochRewardStatus, RewardInfo,
+            StakeReward, StartBlockHeightAndRewards,
+        },
+        serde_snapshot::{self, SerdeStyle},
+        snapshot_archive_info::SnapshotArchiveInfoGetter,
+        snapshot_utils::ArchiveFormat,
+        stakes::Stakes,
+    },
+    solana_sdk::{
+        account::{AccountSharedData, ReadableAccount},
+        clock::Slot,
+        genesis_config::{ClusterType, GenesisConfig},
+        hash::Hash,
+        pubkey::Pubkey,
+        reward_type::RewardType,
+        signature::{Keypair, Signer},
+        stake::state::Delegation,
+    },
+    std::{
+        collections::{BTreeSet, HashMap},
+        env, fs,
+        io::{BufReader, BufWriter, Cursor, Read, Write},
+        process,
+        sync::Arc,
+    },
+};
+
+/// Returns a test bank 21 slots past genesis, a full snapshot of it at slot 11 for incremental
+/// snapshots to be based on, and its genesis config
+fn test_banks() -> (Arc<Bank>, Vec<u8>, GenesisConfig) {
+    let genesis_config = test_genesis_config();
+    let bank = new_test_bank(genesis_test_bank(&genesis_config), 11);
+    let full_snapshot = encode(&bank, &SnapshotKind::Full, Encoding::Unsorted, None);
+    (new_test_bank(bank, 10), full_snapshot, genesis_config)
+}
+
+fn test_bank() -> Arc<Bank> {
+    test_bank_and_genesis_config().0
+}
+
+fn test_bank_and_genesis_config() -> (Arc<Bank>, GenesisConfig) {
+    let genesis_config = test_genesis_config();
+    (
+        new_test_bank(genesis_test_bank(&genesis_config), 21),
+        genesis_config,
+    )
+}
+
+/// Returns the kind of an incremental snapshot based on `full_snapshot_fields`
+fn incremental_kind(full_snapshot_fields: &SnapshotFields) -> SnapshotKind {
+    SnapshotKind::Incremental {
+        base_slot: full_snapshot_fields.bank.slot,
+        base_hash: full_snapshot_fields.accounts_db.accounts_hash,
+    }
+}
+
+fn encode(
+    bank: &Bank,
+    kind: &SnapshotKind,
+    encoding: Encoding,
+    signer: Option<&Keypair>,
+) -> Vec<u8> {
+    let mut serialized_snapshot = Vec::new();
+    snapshot_bank(
+        bank,
+        kind,
+        BTreeSet::from(ALL_SECTIONS),
+        encoding,
+        signer,
+        &mut serialized_snapshot,
+    )
+    .unwrap();
+    serialized_snapshot
+}
+
+fn decode(serialized_snapshot: &[u8]) -> SnapshotFields {
+    decode_snapshot(&mut verify_checksums(Cursor::new(serialized_snapshot)).unwrap()).unwrap()
+}
+
+/// Checks a snapshot's checksums, and returns everything before them
+fn verified_body(serialized_snapshot: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    verify_checksums(Cursor::new(serialized_snapshot))
+        .unwrap()
+        .read_to_end(&mut body)
+        .unwrap();
+    body
+}
+
+#[test]
+fn test_round_trip_full() {
+    let (bank, genesis_config) = test_bank_and_genesis_config();
+    assert_round_trip(&bank, &genesis_config, None);
+}
+
+#[test]
+fn test_round_trip_incremental() {
+    let (bank, full_snapshot, genesis_config) = test_banks();
+    assert_round_trip(&bank, &genesis_config, Some(&full_snapshot));
+}
+
+/// Encodes `bank` canonically, decodes it, then rebuilds it from nothing but the snapshot, and the
+/// full snapshot it is based on if given, checking that the rebuilt bank encodes to the same bytes
+fn assert_round_trip(bank: &Bank, genesis_config: &GenesisConfig, full_snapshot: Option<&[u8]>) {
+    let kind = full_snapshot.map_or(SnapshotKind::Full, |full_snapshot| {
+        incremental_kind(&decode(full_snapshot))
+    });
+    let expected_bank_fields = bank_fields_from_bank(bank, &kind);
+    let expected_accounts_db_fields = AccountsDbFields::new(bank, kind.base_slot()).unwrap();
+    let expected_slot_deltas = bank.status_cache.read().unwrap().root_slot_deltas();
+    // The test bank processed a failing transaction too, so that errors are round tripped
+    assert!(expected_slot_deltas
+        .iter()
+        .any(|(_slot, _is_root, statuses)| {
+            statuses
+                .lock()
+                .unwrap()
+                .values()
+                .flat_map(|(_key_index, key_statuses)| key_statuses)
+                .any(|(_key_slice, result)| result.is_err())
+        }));
+
+    let serialized_snapshot = encode(bank, &kind, Encoding::Canonical, None);
+    let serialized_body = verified_body(&serialized_snapshot);
+    let mut accounts_section = serialized_body.as_slice();
+    let snapshot_fields = decode_snapshot(&mut accounts_section).unwrap();
+    assert_snapshot_fields(
+        &kind,
+        &expected_bank_fields,
+        &expected_accounts_db_fields,
+        &expected_slot_deltas,
+        &snapshot_fields,
+    );
+    assert!(snapshot_fields.header.has_section(Section::Accounts));
+    assert_account_records(bank, kind.base_slot(), accounts_section);
+
+    let kind_name = if full_snapshot.is_some() {
+        "incremental"
+    } else {
+        "full"
+    };
+    let account_path = env::temp_dir().join(format!(
+        "protosnap-test-round-trip-{kind_name}-{}",
+        process::id()
+    ));
+    fs::create_dir_all(&account_path).unwrap();
+    let account_paths = vec![account_path.clone()];
+    let (snapshot_fields, accounts_db) = match full_snapshot {
+        None => {
+            let accounts_db = rebuild_accounts_db(
+                &snapshot_fields.accounts_db,
+                accounts_section,
+                account_paths,
+                &genesis_config.cluster_type,
+            );
+            (snapshot_fields, accounts_db)
+        }
+        Some(full_snapshot) => {
+            let full_body = verified_body(full_snapshot);
+            let mut full_accounts_section = full_body.as_slice();
+            let full_snapshot_fields = decode_snapshot(&mut full_accounts_section).unwrap();
+            let merged_snapshot_fields =
+                merge_incremental_snapshot(full_snapshot_fields, snapshot_fields).unwrap();
+            let accounts_db = rebuild_merged_accounts_db(
+                &merged_snapshot_fields,
+                full_accounts_section,
+                accounts_section,
+                account_paths,
+                &genesis_config.cluster_type,
+            );
+            (merged_snapshot_fields, accounts_db)
+        }
+    };
+    let result = accounts_db
+        .and_then(|accounts_db| {
+            rebuild_bank(snapshot_fields, genesis_config, Arc::new(accounts_db))
+        })
+        .map(|rebuilt_bank| encode(&rebuilt_bank, &kind, Encoding::Canonical, None));
+    fs::remove_dir_all(&account_path).unwrap();
+    assert_eq!(result.unwrap(), serialized_snapshot);
+}
+
+/// Reads back the accounts section, checking it against the accounts in the bank's storages
+fn assert_account_records(bank: &Bank, base_slot: Option<Slot>, accounts_section: &[u8]) {
+    let account_records = AccountRecords::new(accounts_section)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        account_records,
+        storage_records(snapshot_storages(bank, base_slot))
+    );
+}
+
+/// Returns a record of every account in `storages`, in the order a snapshot writes them
+fn storage_records(mut storages: Vec<Arc<AccountStorageEntry>>) -> Vec<AccountRecord> {
+    storages.sort_unstable_by_key(|storage| (storage.slot(), storage.append_vec_id()));
+    storages
+        .into_iter()
+        .flat_map(|storage| {
+            storage
+                .accounts
+                .account_iter()
+                .map(|stored_account| AccountRecord {
+                    pubkey: *stored_account.pubkey(),
+                    slot: storage.slot(),
+                    write_version: stored_account.write_version(),
+                    account: stored_account.clone_account(),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Rebuilds the accounts db from a snapshot's accounts section, checking that its storages are the
+/// snapshotted ones and hold the same records, down to their write versions
+#[test]
+fn test_rebuild_accounts_db() {
+    let bank = test_bank();
+    let serialized_body = verified_body(&encode(
+        &bank,
+        &SnapshotKind::Full,
+        Encoding::Unsorted,
+        None,
+    ));
+    let mut accounts_section = serialized_body.as_slice();
+    let snapshot_fields = decode_snapshot(&mut accounts_section).unwrap();
+    let account_path = env::temp_dir().join(format!("protosnap-test-accounts-{}", process::id()));
+    fs::create_dir_all(&account_path).unwrap();
+    let result = rebuild_accounts_db(
+        &snapshot_fields.accounts_db,
+        accounts_section,
+        vec![account_path.clone()],
+        &ClusterType::Development,
+    )
+    .map(|accounts_db| {
+        let mut storages = accounts_db.get_snapshot_storages(..).0;
+        storages.sort_unstable_by_key(|storage| (storage.slot(), storage.append_vec_id()));
+        let storage_entries: Vec<_> = storages
+            .iter()
+            .map(|storage| StorageEntry {
+                slot: storage.slot(),
+                id: storage.append_vec_id(),
+                accounts_current_len: storage.accounts.len(),
+            })
+            .collect();
+        (storage_entries, storage_records(storages))
+    });
+    fs::remove_dir_all(&account_path).unwrap();
+    let (storage_entries, account_records) = result.unwrap();
+    assert_eq!(storage_entries, snapshot_fields.accounts_db.storage_entries);
+    assert_eq!(
+        account_records,
+        storage_records(snapshot_storages(&bank, None))
+    );
+}
+
+#[test]
+fn test_full_snapshot_has_accounts_hash() {
+    let bank = test_bank();
+    assert_eq!(bank.get_accounts_hash(), None);
+    let snapshot_fields = decode(&encode(
+        &bank,
+        &SnapshotKind::Full,
+        Encoding::Unsorted,
+        None,
+    ));
+    assert_eq!(
+        Some(snapshot_fields.accounts_db.accounts_hash),
+        bank.get_accounts_hash()
+    );
+    assert_ne!(snapshot_fields.accounts_db.accounts_hash.0, Hash::default());
+}
+
+#[test]
+fn test_incremental_merge() {
+    let (bank, full_snapshot, genesis_config) = test_banks();
+    let full_snapshot_fields = decode(&full_snapshot);
+    let kind = incremental_kind(&full_snapshot_fields);
+    assert_ne!(
+        full_snapshot_fields.accounts_db.accounts_hash.0,
+        Hash::default()
+    );
+    let incremental_snapshot = encode(&bank, &kind, Encoding::Canonical, None);
+    // The incremental snapshot was taken before the full accounts hash at its slot was calculated
+    let expected_accounts_db_fields = AccountsDbFields {
+        accounts_hash: decode(&incremental_snapshot).accounts_db.accounts_hash,
+        ..AccountsDbFields::new(&bank, None).unwrap()
+    };
+
+    // Rebuild the merged bank from nothing but the two snapshots
+    let mut full_sections = verify_checksums(Cursor::new(&full_snapshot)).unwrap();
+    let full_snapshot_fields = decode_snapshot(&mut full_sections).unwrap();
+    let mut incremental_sections = verify_checksums(Cursor::new(&incremental_snapshot)).unwrap();
+    let incremental_snapshot_fields = decode_snapshot(&mut incremental_sections).unwrap();
+    let merged_snapshot_fields =
+        merge_incremental_snapshot(full_snapshot_fields, incremental_snapshot_fields).unwrap();
+    assert_snapshot_fields(
+        &SnapshotKind::Full,
+        &bank_fields_from_bank(&bank, &kind),
+        &expected_accounts_db_fields,
+        &bank.status_cache.read().unwrap().root_slot_deltas(),
+        &merged_snapshot_fields,
+    );
+    let account_path = env::temp_dir().join(format!("protosnap-test-merge-{}", process::id()));
+    fs::create_dir_all(&account_path).unwrap();
+    let result = rebuild_merged_accounts_db(
+        &merged_snapshot_fields,
+        full_sections,
+        incremental_sections,
+        vec![account_path.clone()],
+        &ClusterType::Development,
+    )
+    .and_then(|accounts_db| {
+        rebuild_bank(
+            merged_snapshot_fields,
+            &genesis_config,
+            Arc::new(accounts_db),
+        )
+    })
+    .map(|rebuilt_bank| {
+        // The full accounts hash was restored, so the rebuilt bank can be snapshotted
+        // incrementally again
+        let reencoded_snapshot = encode(&rebuilt_bank, &kind, Encoding::Canonical, None);
+        (rebuilt_bank, reencoded_snapshot)
+    });
+    fs::remove_dir_all(&account_path).unwrap();
+    let (rebuilt_bank, reencoded_snapshot) = result.unwrap();
+
+    assert_eq!(
+        storage_records(snapshot_storages(&rebuilt_bank, None)),
+        storage_records(snapshot_storages(&bank, None))
+    );
+    assert_eq!(reencoded_snapshot, incremental_snapshot);
+}
+
+#[test]
+fn test_merge_rejects_base_slot_mismatch() {
+    let (bank, full_snapshot, _genesis_config) = test_banks();
+    let kind = incremental_kind(&decode(&full_snapshot));
+    let incremental_snapshot_fields = decode(&encode(&bank, &kind, Encoding::Unsorted, None));
+    let other_full_snapshot_fields = decode(&encode(
+        &bank,
+        &SnapshotKind::Full,
+        Encoding::Unsorted,
+        None,
+    ));
+    assert!(matches!(
+        merge_incremental_snapshot(other_full_snapshot_fields, incremental_snapshot_fields),
+        Err(SnapshotMergeError::BaseSlotMismatch {
+            base_slot: 11,
+            full_slot: 21,
+        })
+    ));
+}
+
+#[test]
+fn test_merge_rejects_base_hash_mismatch() {
+    let (bank, full_snapshot, _genesis_config) = test_banks();
+    let mut full_snapshot_fields = decode(&full_snapshot);
+    let kind = incremental_kind(&full_snapshot_fields);
+    let incremental_snapshot_fields = decode(&encode(&bank, &kind, Encoding::Unsorted, None));
+    full_snapshot_fields.accounts_db.accounts_hash = AccountsHash(Hash::new_unique());
+    assert!(matches!(
+        merge_incremental_snapshot(full_snapshot_fields, incremental_snapshot_fields),
+        Err(SnapshotMergeError::BaseHashMismatch)
+    ));
+}
+
+#[test]
+fn test_merge_rejects_persistence_mismatch() {
+    let (bank, full_snapshot, _genesis_config) = test_banks();
+    let mut full_snapshot_fields = decode(&full_snapshot);
+    let kind = incremental_kind(&full_snapshot_fields);
+    let incremental_snapshot_fields = decode(&encode(&bank, &kind, Encoding::Unsorted, None));
+    full_snapshot_fields.bank.capitalization += 1;
+    assert!(matches!(
+        merge_incremental_snapshot(full_snapshot_fields, incremental_snapshot_fields),
+        Err(SnapshotMergeError::PersistenceMismatch {
+            field: "full_capitalization"
+        })
+    ));
+}
+
+#[test]
+fn test_merge_requires_persistence() {
+    let (bank, full_snapshot, _genesis_config) = test_banks();
+    let full_snapshot_fields = decode(&full_snapshot);
+    let kind = incremental_kind(&full_snapshot_fields);
+    let mut incremental_snapshot_fields = decode(&encode(&bank, &kind, Encoding::Unsorted, None));
+    incremental_snapshot_fields
+        .bank
+        .incremental_snapshot_persistence = None;
+    assert!(matches!(
+        merge_incremental_snapshot(full_snapshot_fields, incremental_snapshot_fields),
+        Err(SnapshotMergeError::MissingPersistence)
+    ));
+}
+
+#[test]
+fn test_encode_rejects_wrong_base_hash() {
+    let (bank, full_snapshot, _genesis_config) = test_banks();
+    let kind = SnapshotKind::Incremental {
+        base_slot: decode(&full_snapshot).bank.slot,
+        base_hash: AccountsHash(Hash::new_unique()),
+    };
+    let mut err = snapshot_bank(
+        &bank,
+        &kind,
+        BTreeSet::from(ALL_SECTIONS),
+        Encoding::Unsorted,
+        None,
+        &mut Vec::new(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.path_mut().map(|field_path| field_path.to_string()),
+        Some("bank.incremental_snapshot_persistence.full_hash".to_string()),
+        "{err}"
+    );
+    assert!(matches!(err, SnapshotEncodeError::InvalidValue { .. }));
+}
+
+/// Encodes the test bank canonically twice, then rebuilds it and encodes the rebuilt bank
+/// canonically, checking that all three encodings are the same bytes
+#[test]
+fn test_canonical_encoding() {
+    let (bank, genesis_config) = test_bank_and_genesis_config();
+    // The test genesis config has a node with two vote accounts, whose order must be canonical too
+    assert!(bank.epoch_stakes_map().values().any(|epoch_stakes| {
+        epoch_stakes
+            .node_id_to_vote_accounts()
+            .values()
+            .any(|node_vote_accounts| node_vote_accounts.vote_accounts.len() > 1)
+    }));
+    let serialized_snapshot = encode(&bank, &SnapshotKind::Full, Encoding::Canonical, None);
+    assert_eq!(
+        encode(&bank, &SnapshotKind::Full, Encoding::Canonical, None),
+        serialized_snapshot
+    );
+
+    let rebuilt_bank = rebuild_bank(
+        decode(&serialized_snapshot),
+        &genesis_config,
+        Arc::clone(&bank.rc.accounts.accounts_db),
+    )
+    .unwrap();
+    assert_eq!(
+        encode(
+            &rebuilt_bank,
+            &SnapshotKind::Full,
+            Encoding::Canonical,
+            None
+        ),
+        serialized_snapshot
+    );
+}
+
+/// Corrupts each section of a snapshot in turn, checking that the checksums catch it before
+/// anything is decoded
+#[test]
+fn test_checksums_catch_corruption() {
+    let serialized_snapshot = encode(&test_bank(), &SnapshotKind::Full, Encoding::Unsorted, None);
+    let (_body_len, checksums) = read_checksums(&mut Cursor::new(&serialized_snapshot)).unwrap();
+
+    let mut section_start = 0;
+    for section in checksums.sections {
+        let section_len = section.len as usize;
+        let mut corrupted_snapshot = serialized_snapshot.clone();
+        corrupted_snapshot[section_start + section_len / 2] ^= 1;
+        match verify_checksums(Cursor::new(&corrupted_snapshot)) {
+            Err(SnapshotDecodeError::ChecksumMismatch {
+                section: mismatched_section,
+            }) => assert_eq!(mismatched_section, section.name),
+            result => panic!("{}: corruption was not detected: {result:?}", section.name),
+        }
+        section_start += section_len;
+    }
+}
+
+/// Cuts a snapshot short at a few points, checking that its checksums are reported missing
+#[test]
+fn test_checksums_catch_truncation() {
+    let serialized_snapshot = encode(&test_bank(), &SnapshotKind::Full, Encoding::Unsorted, None);
+    let len = serialized_snapshot.len();
+    for truncated_len in [0, 3, len / 2, len - 1] {
+        let truncated_snapshot = &serialized_snapshot[..truncated_len];
+        let result = verify_checksums(Cursor::new(truncated_snapshot));
+        assert!(
+            matches!(result, Err(SnapshotDecodeError::MissingChecksums)),
+            "truncated to {truncated_len} bytes: {result:?}"
+        );
+    }
+}
+
+/// Writes a snapshot whose `Snapshot` message is malformed, but whose checksums match, checking
+/// that decoding reports the malformed protobuf
+#[test]
+fn test_decode_rejects_malformed_snapshot() {
+    let header = ProtoHeader::from(&SnapshotHeader::now(BTreeSet::from(ALL_SECTIONS)));
+    let mut writer = ChecksumWriter::new(Vec::new());
+    writer
+        .write_all(&header.encode_length_delimited_to_vec())
+        .unwrap();
+    writer.end_section("header");
+    // A length prefix, then a field key with the reserved field number 0
+    writer.write_all(&[2, 0, 0]).unwrap();
+    writer.end_section("snapshot");
+    let malformed_snapshot = writer.finish(None).unwrap();
+
+    let mut sections = verify_checksums(Cursor::new(&malformed_snapshot)).unwrap();
+    assert!(matches!(
+        decode_snapshot(&mut sections),
+        Err(SnapshotDecodeError::Protobuf(_))
+    ));
+}
+
+/// Rewrites a snapshot's header with an older version, and without the checksums, the way older
+/// versions were written, checking that it is rejected as unsupported rather than as truncated
+#[test]
+fn test_decode_rejects_older_version() {
+    let serialized_snapshot = encode(&test_bank(), &SnapshotKind::Full, Encoding::Unsorted, None);
+    let serialized_body = verified_body(&serialized_snapshot);
+    let mut sections = serialized_body.as_slice();
+    let mut header = ProtoHeader::decode_length_delimited(&mut sections).unwrap();
+    header.version = SCHEMA_VERSION - 1;
+    let mut older_snapshot = header.encode_length_delimited_to_vec();
+    older_snapshot.extend(sections);
+    assert!(matches!(
+        verify_checksums(Cursor::new(&older_snapshot)),
+        Err(SnapshotDecodeError::UnsupportedVersion { version, supported: SCHEMA_VERSION })
+            if version == SCHEMA_VERSION - 1
+    ));
+}
+
+#[test]
+fn test_decode_rejects_newer_version() {
+    let mut header = ProtoHeader::from(&SnapshotHeader::now(BTreeSet::from(ALL_SECTIONS)));
+    header.version = SCHEMA_VERSION + 1;
+    assert!(matches!(
+        SnapshotHeader::try_from(header),
+        Err(SnapshotDecodeError::UnsupportedVersion { version, supported: SCHEMA_VERSION })
+            if version == SCHEMA_VERSION + 1
+    ));
+}
+
+#[test]
+fn test_decode_rejects_unspecified_section() {
+    let mut header = ProtoHeader::from(&SnapshotHeader::now(BTreeSet::new()));
+    header.sections.push(Section::Unspecified.into());
+    let mut err = SnapshotHeader::try_from(header).unwrap_err();
+    assert_eq!(
+        err.path_mut().map(|field_path| field_path.to_string()),
+        Some("sections[0]".to_string()),
+        "{err}"
+    );
+    assert!(matches!(err, SnapshotDecodeError::InvalidValue { .. }));
+}
+
+/// Leaves out the optional sections, both when encoding a bank and when re-encoding a snapshot of
+/// it, checking that both give the same bytes
+#[test]
+fn test_snapshot_without_sections() {
+    let bank = test_bank();
+    let serialized_snapshot = encode(&bank, &SnapshotKind::Full, Encoding::Canonical, None);
+    let mut without_sections = Vec::new();
+    snapshot_bank(
+        &bank,
+        &SnapshotKind::Full,
+        BTreeSet::new(),
+        Encoding::Canonical,
+        None,
+        &mut without_sections,
+    )
+    .unwrap();
+    let serialized_body = verified_body(&without_sections);
+    let mut sections = serialized_body.as_slice();
+    let snapshot_fields = decode_snapshot(&mut sections).unwrap();
+    assert!(snapshot_fields.header.sections.is_empty());
+    assert!(snapshot_fields.status_cache.is_empty());
+    assert!(sections.is_empty());
+
+    let mut reencoded_snapshot = Vec::new();
+    reencode_snapshot(
+        Cursor::new(&serialized_snapshot),
+        &BTreeSet::new(),
+        Encoding::Canonical,
+        None,
+        &mut reencoded_snapshot,
+    )
+    .unwrap();
+    assert_eq!(reencoded_snapshot, without_sections);
+}
+
+/// Sizes a snapshot's fields, checking that its sections and checksums add up to the whole file
+#[test]
+fn test_field_sizes() {
+    let serialized_snapshot = encode(&test_bank(), &SnapshotKind::Full, Encoding::Unsorted, None);
+    let field_sizes = stats::field_sizes(Cursor::new(&serialized_snapshot)).unwrap();
+    let sections_len: usize = field_sizes
+        .iter()
+        .filter(|field_size| {
+            ["header", "snapshot", "accounts", "checksums"].contains(&field_size.path.as_str())
+        })
+        .map(|field_size| field_size.len)
+        .sum();
+    assert_eq!(sections_len, serialized_snapshot.len());
+}
+
+/// Signs a snapshot, checking that only the signer is trusted, and that an unsigned snapshot is
+/// rejected
+#[test]
+fn test_signing() {
+    let bank = test_bank();
+    let signer = Keypair::new();
+
+    let signed_snapshot = encode(
+        &bank,
+        &SnapshotKind::Full,
+        Encoding::Unsorted,
+        Some(&signer),
+    );
+    let mut signed_body = Vec::new();
+    verify_signature(Cursor::new(&signed_snapshot), &signer.pubkey())
+        .unwrap()
+        .read_to_end(&mut signed_body)
+        .unwrap();
+    assert_eq!(signed_body, verified_body(&signed_snapshot));
+    assert!(matches!(
+        verify_signature(Cursor::new(&signed_snapshot), &Pubkey::new_unique()),
+        Err(SnapshotDecodeError::UntrustedSigner { .. })
+    ));
+
+    let unsigned_snapshot = encode(&bank, &SnapshotKind::Full, Encoding::Unsorted, None);
+    assert!(matches!(
+        verify_signature(Cursor::new(&unsigned_snapshot), &signer.pubkey()),
+        Err(SnapshotDecodeError::MissingSignature)
+    ));
+}
+
+/// Snapshots the test bank with both the bincode `serde_snapshot` format and protosnap, then
+/// checks that both decode to the same fields
+///
+/// NOTE: The bincode format does not keep `FeeRateGovernor::lamports_per_signature`, so it is
+/// ignored here.
+#[test]
+fn test_against_bincode() {
+    let bank = test_bank();
+    let snapshot_storages: Vec<_> = bank
+        .get_snapshot_storages(None)
+        .into_iter()
+        .map(|storage| vec![storage])
+        .collect();
+    let mut stream = BufWriter::new(Vec::new());
+    serde_snapshot::bank_to_stream(SerdeStyle::Newer, &mut stream, &bank, &snapshot_storages)
+        .unwrap();
+    let bincode_snapshot = stream.into_inner().unwrap();
+    let (mut bincode_bank_fields, _accounts_db_fields) = serde_snapshot::fields_from_stream(
+        SerdeStyle::Newer,
+        &mut BufReader::new(bincode_snapshot.as_slice()),
+    )
+    .unwrap();
+
+    let bank_fields = decode(&encode(
+        &bank,
+        &SnapshotKind::Full,
+        Encoding::Unsorted,
+        None,
+    ))
+    .bank;
+    bincode_bank_fields.fee_rate_governor.lamports_per_signature =
+        bank_fields.fee_rate_governor.lamports_per_signature;
+    assert_eq!(
+        diff_bank_fields(&bincode_bank_fields, &bank_fields),
+        Vec::<&str>::new()
+    );
+}
+
+/// Converts a snapshot into a snapshot archive and back, then checks that the bank and the
+/// accounts' latest states survived
+///
+/// NOTE: Writing the archive cleans the rebuilt bank, which drops accounts' older versions and
+/// zero-lamport accounts from the storages, so only what is left after cleaning is compared.
+#[test]
+fn test_archive_round_trip() {
+    let genesis_config = test_genesis_config();
+    let bank = new_test_bank(genesis_test_bank(&genesis_config), 21);
+    let serialized_snapshot = encode(&bank, &SnapshotKind::Full, Encoding::Unsorted, None);
+    let mut sections = verify_checksums(Cursor::new(&serialized_snapshot)).unwrap();
+    let expected = decode_snapshot(&mut sections).unwrap();
+    let expected_accounts = latest_accounts(sections);
+
+    let work_dir = env::temp_dir().join(format!("protosnap-test-{}", process::id()));
+    let result = (|| {
+        let archive_info = snapshot_to_archive(
+            Cursor::new(&serialized_snapshot),
+            &genesis_config,
+            &work_dir.join("to_archive"),
+            &work_dir.join("archives"),
+            ArchiveFormat::TarZstd,
+        )?;
+        let mut round_tripped_snapshot = Vec::new();
+        archive_to_snapshot(
+            archive_info.path(),
+            None,
+            &genesis_config,
+            &work_dir.join("from_archive"),
+            BTreeSet::from(ALL_SECTIONS),
+            Encoding::Unsorted,
+            None,
+            &mut round_tripped_snapshot,
+        )?;
+        Ok::<_, ArchiveError>(round_tripped_snapshot)
+    })();
+    fs::remove_dir_all(&work_dir).unwrap();
+    let round_tripped_snapshot = result.unwrap();
+
+    let mut sections = verify_checksums(Cursor::new(&round_tripped_snapshot)).unwrap();
+    let actual = decode_snapshot(&mut sections).unwrap();
+    assert_eq!(
+        diff_bank_fields(&expected.bank, &actual.bank),
+        Vec::<&str>::new()
+    );
+    assert!(slot_deltas_eq(&expected.status_cache, &actual.status_cache));
+    assert!(latest_accounts(sections) == expected_accounts);
+}
+
+/// Checks that a bank's accounts are verified against its accounts hash once there is one, and that
+/// verifying without one, or against a wrong one, is an error rather than a panic
+#[test]
+fn test_verify_accounts_hash() {
+    let bank = test_bank();
+    assert!(matches!(
+        verify_accounts_hash(&bank, None),
+        Err(ArchiveError::AccountsHash(
+            AccountsHashVerificationError::MissingAccountsHash
+        ))
+    ));
+    let accounts_hash = calculate_accounts_hash(&bank).unwrap();
+    assert_eq!(bank.get_accounts_hash(), Some(accounts_hash));
+    verify_accounts_hash(&bank, None).unwrap();
+
+    let accounts_db = &bank.rc.accounts.accounts_db;
+    accounts_db.set_accounts_hash(
+        bank.slot(),
+        (AccountsHash(Hash::new_unique()), bank.capitalization()),
+    );
+    assert!(matches!(
+        verify_accounts_hash(&bank, None),
+        Err(ArchiveError::AccountsHash(
+            AccountsHashVerificationError::MismatchedAccountsHash
+        ))
+    ));
+}
+
+/// Rebuilds a bank with a genesis config that is not its cluster's, checking that it is rejected
+/// rather than panicking
+#[test]
+fn test_rebuild_rejects_other_genesis_config() {
+    let (bank, genesis_config) = test_bank_and_genesis_config();
+    let snapshot_fields = decode(&encode(
+        &bank,
+        &SnapshotKind::Full,
+        Encoding::Unsorted,
+        None,
+    ));
+    let other_genesis_config = GenesisConfig {
+        creation_time: genesis_config.creation_time + 1,
+        ..genesis_config
+    };
+    let mut err = rebuild_bank(
+        snapshot_fields,
+        &other_genesis_config,
+        Arc::clone(&bank.rc.accounts.accounts_db),
+    )
+    .map(|_| ())
+    .unwrap_err();
+    assert_eq!(
+        err.path_mut().map(|field_path| field_path.to_string()),
+        Some("bank.genesis_creation_time".to_string()),
+        "{err}"
+    );
+    assert!(matches!(err, SnapshotDecodeError::InvalidValue { .. }));
+}
+
+/// Converts a snapshot into a snapshot archive with a genesis config that is not its cluster's,
+/// checking that it is rejected before anything is written
+#[test]
+fn test_archive_rejects_other_genesis_config() {
+    let genesis_config = test_genesis_config();
+    let bank = new_test_bank(genesis_test_bank(&genesis_config), 1);
+    let serialized_snapshot = encode(&bank, &SnapshotKind::Full, Encoding::Unsorted, None);
+    let other_genesis_config = GenesisConfig {
+        ticks_per_slot: genesis_config.ticks_per_slot + 1,
+        ..genesis_config
+    };
+    let work_dir = env::temp_dir().join(format!("protosnap-test-genesis-{}", process::id()));
+    match snapshot_to_archive(
+        Cursor::new(&serialized_snapshot),
+        &other_genesis_config,
+        &work_dir.join("to_archive"),
+        &work_dir.join("archives"),
+        ArchiveFormat::TarZstd,
+    ) {
+        Err(ArchiveError::Decode(mut err)) => {
+            assert_eq!(err.path_mut().unwrap().to_string(), "bank.ticks_per_slot");
+        }
+        result => panic!("other genesis config was not rejected: {result:?}"),
+    }
+    assert!(!work_dir.exists());
+}
+
+/// Returns the latest state of each account in the accounts section, leaving out zero-lamport
+/// accounts
+fn latest_accounts(accounts_section: impl Read) -> HashMap<Pubkey, AccountSharedData> {
+    let mut accounts = HashMap::new();
+    for account_record in AccountRecords::new(accounts_section) {
+        // NOTE: Records are in slot order, so later ones are newer
+        let account_record = account_record.unwrap();
+        accounts.insert(account_record.pubkey, account_record.account);
+    }
+    accounts.retain(|_pubkey, account| account.lamports() != 0);
+    accounts
+}
+
+#[test]
+fn test_decode_rejects_short_pubkey() {
+    let mut bank = test_proto_bank();
+    bank.stakes.as_mut().unwrap().vote_accounts[0].pubkey.pop();
+    let err = decode_bank_error(bank, "stakes.vote_accounts[0].pubkey");
+    assert!(matches!(
+        err,
+        SnapshotDecodeError::InvalidLength {
+            expected: 32,
+            actual: 31,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_decode_rejects_corrupt_vote_account() {
+    let mut bank = test_proto_bank();
+    let vote_account = bank.stakes.as_mut().unwrap().vote_accounts[0]
+        .vote_account
+        .as_mut()
+        .unwrap();
+    vote_account.data.fill(0xff);
+    let err = decode_bank_error(bank, "stakes.vote_accounts[0].vote_account");
+    assert!(matches!(
+        err,
+        SnapshotDecodeError::InvalidVoteAccount { .. }
+    ));
+}
+
+#[test]
+fn test_decode_rejects_duplicate_vote_account() {
+    let mut bank = test_proto_bank();
+    let vote_accounts = &mut bank.stakes.as_mut().unwrap().vote_accounts;
+    vote_accounts.push(vote_accounts[0].clone());
+    let path = format!("stakes.vote_accounts[{}].pubkey", vote_accounts.len() - 1);
+    let err = decode_bank_error(bank, &path);
+    assert!(matches!(err, SnapshotDecodeError::DuplicateKey { .. }));
+}
+
+#[test]
+fn test_decode_rejects_stale_last_hash() {
+    let mut bank = test_proto_bank();
+    let blockhash_queue = bank.blockhash_queue.as_mut().unwrap();
+    let oldest_age = blockhash_queue
+        .ages
+        .iter()
+        .min_by_key(|age| age.hash_index)
+        .unwrap();
+    blockhash_queue.last_hash = Some(oldest_age.hash.clone());
+    let err = decode_bank_error(bank, "blockhash_queue.last_hash");
+    assert!(matches!(err, SnapshotDecodeError::InvalidValue { .. }));
+}
+
+#[test]
+fn test_decode_rejects_duplicate_blockhash() {
+    let mut bank = test_proto_bank();
+    let ages = &mut bank.blockhash_queue.as_mut().unwrap().ages;
+    ages.push(ages[0].clone());
+    let path = format!("blockhash_queue.ages[{}].hash", ages.len() - 1);
+    let err = decode_bank_error(bank, &path);
+    assert!(matches!(err, SnapshotDecodeError::DuplicateKey { .. }));
+}
+
+#[test]
+fn test_decode_rejects_hash_index_past_last_hash_index() {
+    let mut bank = test_proto_bank();
+    let blockhash_queue = bank.blockhash_queue.as_mut().unwrap();
+    blockhash_queue.ages[0].hash_index = blockhash_queue.last_hash_index + 1;
+    let err = decode_bank_error(bank, "blockhash_queue.ages[0].hash_index");
+    assert!(matches!(err, SnapshotDecodeError::InvalidValue { .. }));
+}
+
+#[test]
+fn test_decode_rejects_missing_last_hash() {
+    let mut bank = test_proto_bank();
+    let blockhash_queue = bank.blockhash_queue.as_mut().unwrap();
+    assert!(!blockhash_queue.ages.is_empty());
+    blockhash_queue.last_hash = None;
+    let err = decode_bank_error(bank, "blockhash_queue.last_hash");
+    assert!(matches!(err, SnapshotDecodeError::MissingField { .. }));
+}
+
+#[test]
+fn test_decode_rejects_wrong_epoch_total_stake() {
+    let mut bank = test_proto_bank();
+    bank.epoch_stakes[0].total_stake += 1;
+    let err = decode_bank_error(bank, "epoch_stakes[0].total_stake");
+    assert!(matches!(err, SnapshotDecodeError::InvalidValue { .. }));
+}
+
+#[test]
+fn test_decode_rejects_wrong_node_total_stake() {
+    let mut bank = test_proto_bank();
+    bank.epoch_stakes[0].node_ids_to_vote_accounts[0].total_stake += 1;
+    let err = decode_bank_error(
+        bank,
+        "epoch_stakes[0].node_ids_to_vote_accounts[0].total_stake",
+    );
+    assert!(matches!(err, SnapshotDecodeError::InvalidValue { .. }));
+}
+
+#[test]
+fn test_epoch_rewards_round_trip() {
+    let reward_types = [
+        (RewardType::Fee, None),
+        (RewardType::Rent, None),
+        (RewardType::Staking, Some(100)),
+        (RewardType::Voting, Some(5)),
+    ];
+    let calculated_epoch_stake_rewards = reward_types
+        .into_iter()
+        .enumerate()
+        .map(|(i, (reward_type, commission))| StakeReward {
+            stake_pubkey: Pubkey::new_unique(),
+            stake_reward_info: RewardInfo {
+                reward_type,
+                lamports: i as i64 * 1_000,
+                post_balance: 1_000_000 + i as u64,
+                commission,
+            },
+            stake_account: AccountSharedData::new(1_000_000 + i as u64, 8, &Pubkey::new_unique()),
+        })
+        .collect();
+    let start_block_height_and_rewards = StartBlockHeightAndRewards {
+        start_block_height: 42,
+        calculated_epoch_stake_rewards: Arc::new(calculated_epoch_stake_rewards),
+    };
+
+    let mut bank = test_proto_bank();
+    bank.epoch_rewards =
+        Some(ProtoEpochRewards::try_from(&start_block_height_and_rewards).unwrap());
+    assert_eq!(
+        decode_bank(bank).unwrap().epoch_reward_status,
+        EpochRewardStatus::Active(start_block_height_and_rewards)
+    );
+}
+
+#[test]
+fn test_decode_rejects_unknown_reward_kind() {
+    let mut bank = test_proto_bank();
+    bank.epoch_rewards = Some(test_epoch_rewards(ProtoEpochStakeRewardInfo {
+        reward_kind: 99,
+        ..ProtoEpochStakeRewardInfo::default()
+    }));
+    let err = decode_bank_error(
+        bank,
+        "epoch_rewards.epoch_stake_rewards[0].stake_reward_info.reward_kind",
+    );
+    assert!(matches!(err, SnapshotDecodeError::InvalidValue { .. }));
+}
+
+#[test]
+fn test_decode_rejects_commission_out_of_range() {
+    let mut bank = test_proto_bank();
+    bank.epoch_rewards = Some(test_epoch_rewards(ProtoEpochStakeRewardInfo {
+        commission: Some(256),
+        ..ProtoEpochStakeRewardInfo::default()
+    }));
+    let err = decode_bank_error(
+        bank,
+        "epoch_rewards.epoch_stake_rewards[0].stake_reward_info.commission",
+    );
+    assert!(matches!(err, SnapshotDecodeError::OutOfRange { .. }));
+}
+
+#[test]
+fn test_decode_rejects_zero_hard_fork_count() {
+    let mut bank = test_proto_bank();
+    bank.hard_forks[0].count = 0;
+    let err = decode_bank_error(bank, "hard_forks[0].count");
+    assert!(matches!(err, SnapshotDecodeError::InvalidValue { .. }));
+}
+
+#[test]
+fn test_decode_rejects_duplicate_hard_fork_slot() {
+    let mut bank = test_proto_bank();
+    bank.hard_forks.push(ProtoHardFork {
+        slot: bank.hard_forks[0].slot,
+        count: 1,
+    });
+    let err = decode_bank_error(bank, "hard_forks[1].slot");
+    assert!(matches!(err, SnapshotDecodeError::DuplicateKey { .. }));
+}
+
+#[test]
+fn test_decode_rejects_inconsistent_ns_per_slot() {
+    let mut bank = test_proto_bank();
+    let ns_per_slot = u128::from(bank.ns_per_slot.clone().unwrap());
+    bank.ns_per_slot = Some((ns_per_slot + 1).into());
+    let err = decode_bank_error(bank, "ns_per_slot");
+    assert!(matches!(err, SnapshotDecodeError::InvalidValue { .. }));
+}
+
+#[test]
+fn test_decode_rejects_inconsistent_max_tick_height() {
+    let mut bank = test_proto_bank();
+    bank.max_tick_height += 1;
+    let err = decode_bank_error(bank, "max_tick_height");
+    assert!(matches!(err, SnapshotDecodeError::InvalidValue { .. }));
+}
+
+#[test]
+fn test_decode_rejects_inconsistent_epoch() {
+    let mut bank = test_proto_bank();
+    bank.epoch += 1;
+    let err = decode_bank_error(bank, "epoch");
+    assert!(matches!(err, SnapshotDecodeError::InvalidValue { .. }));
+}
+
+#[test]
+fn test_uint128_round_trip() {
+    for value in [0, 1, u128::from(u64::MAX), 1 << 64, u128::MAX] {
+        let proto = ProtoUint128::from(value);
+        assert_eq!(
+            u128::from(ProtoUint128::decode(proto.encode_to_vec().as_slice()).unwrap()),
+            value
+        );
+    }
+    assert_eq!(ProtoUint128::from(1 << 64), ProtoUint128 { hi: 1, lo: 0 });
+    assert_eq!(
+        ProtoUint128::from(u128::MAX),
+        ProtoUint128 {
+            hi: u64::MAX,
+            lo: u64::MAX,
+        }
+    );
+}
+
+/// Returns the test bank encoded, which decodes fine until a test breaks it
+fn test_proto_bank() -> ProtoBank {
+    let bank = ProtoBank::try_from(&*test_bank()).unwrap();
+    assert!(!bank.hard_forks.is_empty());
+    decode_bank(bank.clone()).unwrap();
+    bank
+}
+
+/// Returns epoch rewards with one stake reward, which has `stake_reward_info`
+fn test_epoch_rewards(stake_reward_info: ProtoEpochStakeRewardInfo) -> ProtoEpochRewards {
+    ProtoEpochRewards {
+        start_block_height: 0,
+        epoch_stake_rewards: vec![ProtoEpochStakeReward {
+            stake_pubkey: Pubkey::new_unique().to_bytes().into(),
+            stake_account: Some(AccountSharedData::default().into()),
+            stake_reward_info: Some(stake_reward_info),
+        }],
+    }
+}
+
+/// Decodes `bank`, checking that it fails at `path`, and returns the error
+fn decode_bank_error(bank: ProtoBank, path: &str) -> SnapshotDecodeError {
+    let mut err = decode_bank(bank).map(|_| ()).unwrap_err();
+    assert_eq!(
+        err.path_mut().map(|field_path| field_path.to_string()),
+        Some(path.to_string()),
+        "{err}"
+    );
+    err
+}
+
+/// Returns the fields a snapshot of `bank` is expected to decode to, i.e. what
+/// `get_fields_to_serialize` writes, plus the incremental snapshot persistence if `kind` is
+/// incremental
+fn bank_fields_from_bank(bank: &Bank, kind: &SnapshotKind) -> BankFieldsToDeserialize {
+    let ancestors = HashMap::<Slot, usize>::from(&bank.ancestors);
+    let bank_fields = bank.get_fields_to_serialize(&ancestors);
+    let stakes = Stakes::<Delegation>::from(bank_fields.stakes.0.read().unwrap().clone());
+    let blockhash_queue = bank_fields.blockhash_queue.read().unwrap().clone();
+    let hard_forks = bank_fields.hard_forks.read().unwrap().clone();
+    BankFieldsToDeserialize {
+        blockhash_queue,
+        ancestors: bank_fields.ancestors.clone(),
+        hash: bank_fields.hash,
+        parent_hash: bank_fields.parent_hash,
+        parent_slot: bank_fields.parent_slot,
+        hard_forks,
+        transaction_count: bank_fields.transaction_count,
+        tick_height: bank_fields.tick_height,
+        signature_count: bank_fields.signature_count,
+        capitalization: bank_fields.capitalization,
+        max_tick_height: bank_fields.max_tick_height,
+        hashes_per_tick: bank_fields.hashes_per_tick,
+        ticks_per_slot: bank_fields.ticks_per_slot,
+        ns_per_slot: bank_fields.ns_per_slot,
+        genesis_creation_time: bank_fields.genesis_creation_time,
+        slots_per_year: bank_fields.slots_per_year,
+        slot: bank_fields.slot,
+        epoch: bank_fields.epoch,
+        block_height: bank_fields.block_height,
+        collector_id: bank_fields.collector_id,
+        collector_fees: bank_fields.collector_fees,
+        fee_calculator: bank_fields.fee_calculator,
+        fee_rate_governor: bank_fields.fee_rate_governor,
+        collected_rent: bank_fields.collected_rent,
+        rent_collector: bank_fields.rent_collector,
+        epoch_schedule: bank_fields.epoch_schedule,
+        inflation: bank_fields.inflation,
+        stakes,
+        epoch_stakes: bank_fields.epoch_stakes.clone(),
+        is_delta: bank_fields.is_delta,
+        accounts_data_len: bank_fields.accounts_data_len,
+        incremental_snapshot_persistence: match kind {
+            SnapshotKind::Full => bank.incremental_snapshot_persistence.clone(),
+            SnapshotKind::Incremental {
+                base_slot,
+                base_hash,
+            } => Some(incremental_snapshot_persistence(bank, *base_slot, base_hash).unwrap()),
+        },
+        epoch_accounts_hash: bank
+            .get_epoch_accounts_hash_to_serialize()
+            .map(|epoch_accounts_hash| *epoch_accounts_hash.as_ref()),
+        epoch_reward_status: bank
+            .get_epoch_reward_status_to_serialize()
+            .cloned()
+            .unwrap_or(EpochRewardStatus::Inactive),
+    }
+}
+
+/// Checks decoded snapshot fields against the expected ones
+fn assert_snapshot_fields(
+    expected_kind: &SnapshotKind,
+    expected_bank_fields: &BankFieldsToDeserialize,
+    expected_accounts_db_fields: &AccountsDbFields,
+    expected_slot_deltas: &[BankSlotDelta],
+    actual: &SnapshotFields,
+) {
+    assert_eq!(actual.kind, *expected_kind);
+    assert_eq!(
+        diff_bank_fields(expected_bank_fields, &actual.bank),
+        Vec::<&str>::new()
+    );
+    assert_eq!(actual.accounts_db, *expected_accounts_db_fields);
+    assert!(slot_deltas_eq(expected_slot_deltas, &actual.status_cache));
+}